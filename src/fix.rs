@@ -0,0 +1,325 @@
+use crate::header::{self, Exposed, Exposing, Import};
+use crate::importfinder::Position;
+use anyhow::{anyhow, bail, Result};
+use similar::TextDiff;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::PathBuf;
+
+/// New source for a file whose imports we fixed.
+#[derive(Debug)]
+pub struct Fix {
+    /// Where the file is, relative to the working directory
+    pub path: PathBuf,
+    pub before: String,
+    pub after: String,
+}
+
+impl Fix {
+    pub fn unified_diff(&self) -> String {
+        TextDiff::from_lines(&self.before, &self.after)
+            .unified_diff()
+            .context_radius(3)
+            .header(
+                &format!("a/{}", self.path.display()),
+                &format!("b/{}", self.path.display()),
+            )
+            .to_string()
+    }
+}
+
+/// An import, plus the lines it takes up in the source (0-based, since
+/// imports can span several lines.)
+struct Statement {
+    import: Import,
+    lines: Range<usize>,
+}
+
+impl Statement {
+    /// What references in the module body are qualified with
+    fn qualifier(&self) -> &str {
+        self.import.alias.as_ref().unwrap_or(&self.import.name)
+    }
+}
+
+/// Rewrite imports of the modules in `renames` to import their replacements
+/// instead. Aliases and exposing lists stay as they are. If the replacement
+/// is already imported, we merge into that import instead of adding a
+/// duplicate, and point qualified references at it.
+pub fn rewrite(source: &str, renames: &BTreeMap<String, String>) -> Result<String> {
+    let lines: Vec<&str> = source.split('\n').collect();
+
+    let statements: Vec<Statement> = header::parse_with_references(source)
+        .imports
+        .into_iter()
+        .map(|import| {
+            let lines = statement_lines(&lines, &import);
+            Statement { import, lines }
+        })
+        .collect();
+
+    let renamed = |statement: &Statement| {
+        renames
+            .get(&statement.import.name)
+            .filter(|replacement| **replacement != statement.import.name)
+    };
+
+    // which statement ends up importing each replacement: one that already
+    // imports it, or else the first one we're renaming to it
+    let mut targets: BTreeMap<&str, usize> = BTreeMap::new();
+    for (index, statement) in statements.iter().enumerate() {
+        if renamed(statement).is_none() {
+            targets.insert(&statement.import.name, index);
+        }
+    }
+    for (index, statement) in statements.iter().enumerate() {
+        if let Some(replacement) = renamed(statement) {
+            targets.entry(replacement).or_insert(index);
+        }
+    }
+
+    // keep whatever line endings the file already uses
+    let newline = if source.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+
+    let mut edits = Edits::new(&lines, newline);
+    let mut merged_exposing: BTreeMap<usize, Option<Exposing>> = BTreeMap::new();
+
+    for (index, statement) in statements.iter().enumerate() {
+        let replacement = match renamed(statement) {
+            Some(replacement) => replacement,
+            None => continue,
+        };
+
+        let target_index = targets[replacement.as_str()];
+        let target = &statements[target_index];
+
+        // references qualified with the old module name need the new one
+        let new_qualifier = target.import.alias.as_ref().unwrap_or(replacement);
+        if statement.qualifier() != new_qualifier {
+            for reference in statement.import.references.iter() {
+                if let Some((qualifier, _)) = reference.written.rsplit_once('.') {
+                    if qualifier == statement.qualifier() {
+                        edits.replace(reference.position, qualifier, new_qualifier)?;
+                    }
+                }
+            }
+        }
+
+        if target_index == index {
+            edits.replace(
+                statement.import.position,
+                &statement.import.name,
+                replacement,
+            )?;
+        } else {
+            let exposing = merged_exposing
+                .entry(target_index)
+                .or_insert_with(|| target.import.exposing.clone());
+            *exposing = merge(exposing.take(), statement.import.exposing.as_ref());
+
+            edits.replace_lines(statement.lines.clone(), None);
+        }
+    }
+
+    // if we merged in something new, the target import has to be written
+    // out again
+    for (target_index, exposing) in merged_exposing {
+        let target = &statements[target_index];
+        if exposing == target.import.exposing {
+            continue;
+        }
+
+        let name = renamed(target).unwrap_or(&target.import.name);
+
+        let mut import = format!("import {}", name);
+        if let Some(alias) = &target.import.alias {
+            import.push_str(&format!(" as {}", alias));
+        }
+        if let Some(exposing) = &exposing {
+            import.push_str(&format!(" exposing ({})", render(exposing)));
+        }
+
+        edits.replace_lines(target.lines.clone(), Some(import));
+    }
+
+    edits.apply(source)
+}
+
+/// Find the lines an import takes up. Imports have to start at the
+/// beginning of a line, and anything after that has to be indented.
+fn statement_lines(lines: &[&str], import: &Import) -> Range<usize> {
+    let name_line = import.position.row.saturating_sub(1);
+
+    let start = (0..=name_line)
+        .rev()
+        .find(|&line| lines[line].starts_with("import"))
+        .unwrap_or(name_line);
+
+    let mut end = name_line + 1;
+    while end < lines.len() && lines[end].starts_with([' ', '\t']) {
+        end += 1;
+    }
+
+    start..end
+}
+
+fn merge(existing: Option<Exposing>, new: Option<&Exposing>) -> Option<Exposing> {
+    match (existing, new) {
+        (existing, None) => existing,
+        (None, Some(new)) => Some(new.clone()),
+        (Some(Exposing::Everything), _) | (_, Some(Exposing::Everything)) => {
+            Some(Exposing::Everything)
+        }
+        (Some(Exposing::Explicit(mut existing)), Some(Exposing::Explicit(new))) => {
+            for item in new {
+                match existing
+                    .iter_mut()
+                    .find(|existing_item| existing_item.name() == item.name())
+                {
+                    Some(Exposed::Type { constructors, .. }) => {
+                        if let Exposed::Type {
+                            constructors: true, ..
+                        } = item
+                        {
+                            *constructors = true;
+                        }
+                    }
+                    Some(_) => (),
+                    None => existing.push(item.clone()),
+                }
+            }
+
+            Some(Exposing::Explicit(existing))
+        }
+    }
+}
+
+fn render(exposing: &Exposing) -> String {
+    match exposing {
+        Exposing::Everything => String::from(".."),
+        Exposing::Explicit(items) => items
+            .iter()
+            .map(|item| match item {
+                Exposed::Type {
+                    name,
+                    constructors: true,
+                } => format!("{}(..)", name),
+                _ => item.name(),
+            })
+            .collect::<Vec<String>>()
+            .join(", "),
+    }
+}
+
+/// Changes to a source file, kept as byte ranges so we can make them all at
+/// once at the end.
+struct Edits<'a> {
+    lines: &'a [&'a str],
+    newline: &'a str,
+    line_starts: Vec<usize>,
+    edits: Vec<(Range<usize>, String)>,
+}
+
+impl<'a> Edits<'a> {
+    fn new(lines: &'a [&'a str], newline: &'a str) -> Edits<'a> {
+        let mut line_starts = Vec::with_capacity(lines.len());
+        let mut offset = 0;
+
+        for line in lines {
+            line_starts.push(offset);
+            offset += line.len() + 1;
+        }
+
+        Edits {
+            lines,
+            newline,
+            line_starts,
+            edits: Vec::new(),
+        }
+    }
+
+    /// Replace `old` (which has to be at `position`) with `new`.
+    fn replace(&mut self, position: Position, old: &str, new: &str) -> Result<()> {
+        let row = position.row.saturating_sub(1);
+        let line = self
+            .lines
+            .get(row)
+            .ok_or_else(|| anyhow!("there is no line {}", position.row))?;
+
+        let column = match line.char_indices().nth(position.column) {
+            Some((column, _)) => column,
+            None => bail!(
+                "there is no column {} on line {}",
+                position.column,
+                position.row
+            ),
+        };
+
+        if !line[column..].starts_with(old) {
+            bail!(
+                "expected to find {} at {}:{}",
+                old,
+                position.row,
+                position.column
+            );
+        }
+
+        let start = self.line_starts[row] + column;
+        self.edits.push((start..start + old.len(), new.to_string()));
+
+        Ok(())
+    }
+
+    /// Replace whole lines, or remove them entirely.
+    fn replace_lines(&mut self, lines: Range<usize>, new: Option<String>) {
+        let start = self.line_starts[lines.start];
+        let end =
+            self.line_starts.get(lines.end).copied().unwrap_or_else(|| {
+                self.line_starts[lines.end - 1] + self.lines[lines.end - 1].len()
+            });
+
+        self.edits.push((
+            start..end,
+            match new {
+                Some(new) if lines.end < self.lines.len() => format!("{}{}", new, self.newline),
+                Some(new) => new,
+                None => String::new(),
+            },
+        ));
+    }
+
+    fn apply(mut self, source: &str) -> Result<String> {
+        self.edits
+            .sort_by_key(|(range, _)| (range.start, range.end));
+
+        // a line replacement covers any smaller edits inside it (like
+        // renaming the module in an import we're writing out again anyway)
+        let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+        for (range, new) in self.edits {
+            match edits.last_mut() {
+                Some((last, _)) if range.start < last.end => {
+                    if range.end > last.end {
+                        bail!("tried to make overlapping changes");
+                    }
+                }
+                _ => edits.push((range, new)),
+            }
+        }
+
+        let mut out = String::with_capacity(source.len());
+        let mut offset = 0;
+
+        for (range, new) in edits {
+            out.push_str(&source[offset..range.start]);
+            out.push_str(&new);
+            offset = range.end;
+        }
+        out.push_str(&source[offset..]);
+
+        Ok(out)
+    }
+}
@@ -0,0 +1,432 @@
+use crate::importfinder::Position;
+use crate::lexer::{Lexer, Token, TokenKind};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::iter::Peekable;
+
+/// The parts of an Elm module header we care about: the module declaration
+/// and the import block that follows it.
+#[derive(Debug, Default)]
+pub struct Header {
+    pub module: Option<String>,
+    pub imports: Vec<Import>,
+}
+
+#[derive(Debug)]
+pub struct Import {
+    pub name: String,
+    pub alias: Option<String>,
+    pub exposing: Option<Exposing>,
+    pub position: Position,
+    pub references: Vec<Reference>,
+}
+
+/// A place in the module body that refers to something from an import,
+/// either qualified (`Attr.style`) or through the exposing list (`style`)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Reference {
+    /// The unqualified name, as it would appear in an exposing list (`style`
+    /// or `(|.)`)
+    pub name: String,
+
+    /// The reference as it was written in the source (`Attr.style`)
+    pub written: String,
+
+    pub position: Position,
+}
+
+/// What an import brings into scope unqualified.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Exposing {
+    /// `exposing (..)`
+    Everything,
+
+    /// `exposing (Html, div, (|.), Maybe(..))`
+    Explicit(Vec<Exposed>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Exposed {
+    /// A lowercase value like `div`
+    Value(String),
+
+    /// An operator like `(|.)`, stored without the parentheses
+    Operator(String),
+
+    /// A type, and whether its constructors are exposed too (`Maybe(..)`)
+    Type { name: String, constructors: bool },
+}
+
+impl Exposed {
+    /// The name as it would appear in an exposing list, e.g. `div`, `Maybe`,
+    /// or `(|.)`
+    pub fn name(&self) -> String {
+        match self {
+            Exposed::Value(name) => name.to_string(),
+            Exposed::Operator(operator) => format!("({})", operator),
+            Exposed::Type { name, .. } => name.to_string(),
+        }
+    }
+}
+
+/// Read the module header from some Elm source. This stops as soon as the
+/// import block ends, since the compiler doesn't allow imports anywhere else.
+pub fn parse(source: &str) -> Header {
+    header(&mut Lexer::new(source).peekable())
+}
+
+/// Read the module header, then keep going through the module body to find
+/// references to imported values. This is a lot slower than just reading the
+/// header, so only do it if you need it!
+pub fn parse_with_references(source: &str) -> Header {
+    let mut tokens = Lexer::new(source).peekable();
+    let mut header = header(&mut tokens);
+
+    references(&mut header, tokens);
+
+    header
+}
+
+fn header(tokens: &mut Peekable<Lexer>) -> Header {
+    let mut header = Header::default();
+
+    if let Some(name) = module_declaration(tokens) {
+        header.module = Some(name);
+    }
+
+    while let Some(Token {
+        kind: TokenKind::LowerName("import"),
+        ..
+    }) = tokens.peek()
+    {
+        tokens.next();
+
+        if let Some(import) = import(tokens) {
+            header.imports.push(import);
+        }
+    }
+
+    header
+}
+
+/// `module Foo exposing (..)`, `port module Foo exposing (..)`, or
+/// `effect module Foo where { command = MyCmd } exposing (..)`
+fn module_declaration(tokens: &mut Peekable<Lexer>) -> Option<String> {
+    if let Some(Token {
+        kind: TokenKind::LowerName("port"),
+        ..
+    })
+    | Some(Token {
+        kind: TokenKind::LowerName("effect"),
+        ..
+    }) = tokens.peek()
+    {
+        tokens.next();
+    }
+
+    match tokens.peek() {
+        Some(Token {
+            kind: TokenKind::LowerName("module"),
+            ..
+        }) => {
+            tokens.next();
+        }
+        _ => return None,
+    };
+
+    let name = match tokens.next() {
+        Some(Token {
+            kind: TokenKind::UpperName(name),
+            ..
+        }) => name.to_string(),
+        _ => return None,
+    };
+
+    while let Some(token) = tokens.next() {
+        match token.kind {
+            TokenKind::LowerName("exposing") => {
+                skip_balanced(tokens, "(", ")");
+                break;
+            }
+            TokenKind::Symbol("{") => skip_balanced_rest(tokens, "{", "}"),
+            _ => (),
+        }
+    }
+
+    Some(name)
+}
+
+/// `import Foo`, `import Foo as F`, `import Foo exposing (bar)`, or any
+/// combination thereof, possibly spread over several lines.
+fn import(tokens: &mut Peekable<Lexer>) -> Option<Import> {
+    let mut out = match tokens.next() {
+        Some(Token {
+            kind: TokenKind::UpperName(name),
+            position,
+        }) => Import {
+            name: name.to_string(),
+            alias: None,
+            exposing: None,
+            position,
+            references: Vec::new(),
+        },
+        _ => return None,
+    };
+
+    if let Some(Token {
+        kind: TokenKind::LowerName("as"),
+        ..
+    }) = tokens.peek()
+    {
+        tokens.next();
+
+        if let Some(Token {
+            kind: TokenKind::UpperName(alias),
+            ..
+        }) = tokens.peek()
+        {
+            out.alias = Some(alias.to_string());
+            tokens.next();
+        }
+    }
+
+    if let Some(Token {
+        kind: TokenKind::LowerName("exposing"),
+        ..
+    }) = tokens.peek()
+    {
+        tokens.next();
+        out.exposing = exposing(tokens);
+    }
+
+    Some(out)
+}
+
+/// The parenthesized list after `exposing`.
+fn exposing(tokens: &mut Peekable<Lexer>) -> Option<Exposing> {
+    match tokens.next() {
+        Some(Token {
+            kind: TokenKind::Symbol("("),
+            ..
+        }) => (),
+        _ => return None,
+    }
+
+    let mut everything = false;
+    let mut exposed = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        match token.kind {
+            TokenKind::Symbol(")") => break,
+            TokenKind::Symbol("..") => everything = true,
+            TokenKind::LowerName(name) => exposed.push(Exposed::Value(name.to_string())),
+            TokenKind::UpperName(name) => {
+                let constructors = match tokens.peek() {
+                    Some(Token {
+                        kind: TokenKind::Symbol("("),
+                        ..
+                    }) => {
+                        skip_balanced(tokens, "(", ")");
+                        true
+                    }
+                    _ => false,
+                };
+
+                exposed.push(Exposed::Type {
+                    name: name.to_string(),
+                    constructors,
+                })
+            }
+            TokenKind::Symbol("(") => {
+                if let Some(Token {
+                    kind: TokenKind::Symbol(operator),
+                    ..
+                }) = tokens.peek()
+                {
+                    exposed.push(Exposed::Operator(operator.to_string()));
+                }
+                skip_balanced_rest(tokens, "(", ")");
+            }
+            _ => (),
+        }
+    }
+
+    if everything {
+        Some(Exposing::Everything)
+    } else {
+        Some(Exposing::Explicit(exposed))
+    }
+}
+
+/// Words the compiler reserves for itself. These never refer to anything
+/// imported.
+const KEYWORDS: &[&str] = &[
+    "if", "then", "else", "case", "of", "let", "in", "type", "alias", "port", "module", "import",
+    "exposing", "as", "where", "infix",
+];
+
+/// Symbols that are part of the syntax instead of operators.
+const PUNCTUATION: &[&str] = &[
+    "=", "(", ")", "[", "]", "{", "}", ",", "|", "->", ":", "\\", "..", ".",
+];
+
+/// Attach every reference in the module body to the import it came from.
+///
+/// Unqualified names only count when an import lists them explicitly. We
+/// can't tell what `exposing (..)` brings in without reading the imported
+/// module, and guessing would turn every local variable into a reference.
+fn references(header: &mut Header, tokens: Peekable<Lexer>) {
+    let tokens: Vec<Token> = tokens.collect();
+    let defined = definitions(&tokens);
+
+    let mut previous: Option<TokenKind> = None;
+
+    for (index, token) in tokens.iter().enumerate() {
+        match token.kind {
+            // `model.style` and `.style` are record accesses, not references
+            TokenKind::LowerName(_) if previous == Some(TokenKind::Symbol(".")) => (),
+
+            TokenKind::LowerName(written) | TokenKind::UpperName(written) => {
+                match written.rsplit_once('.') {
+                    Some((qualifier, name)) => {
+                        for import in header.imports.iter_mut() {
+                            if import.alias.as_ref().unwrap_or(&import.name) == qualifier {
+                                import.references.push(Reference {
+                                    name: name.to_string(),
+                                    written: written.to_string(),
+                                    position: token.position,
+                                });
+                            }
+                        }
+                    }
+
+                    None => {
+                        // `{ style = ... }` and `{ model | style = ... }` are
+                        // record fields, not references
+                        let is_field = matches!(
+                            previous,
+                            Some(TokenKind::Symbol("{"))
+                                | Some(TokenKind::Symbol(","))
+                                | Some(TokenKind::Symbol("|"))
+                        ) && matches!(
+                            tokens.get(index + 1).map(|next| next.kind),
+                            Some(TokenKind::Symbol("=")) | Some(TokenKind::Symbol(":"))
+                        );
+
+                        if !is_field && !KEYWORDS.contains(&written) && !defined.contains(written) {
+                            add_unqualified(header, written, written, token.position);
+                        }
+                    }
+                }
+            }
+
+            TokenKind::Symbol(operator) if !PUNCTUATION.contains(&operator) => {
+                add_unqualified(header, &format!("({})", operator), operator, token.position)
+            }
+
+            _ => (),
+        }
+
+        previous = Some(token.kind);
+    }
+}
+
+/// Names the module defines for itself at the top level: values, ports,
+/// types, and constructors. These shadow anything an import exposes.
+fn definitions<'a>(tokens: &[Token<'a>]) -> BTreeSet<&'a str> {
+    let mut out = BTreeSet::new();
+    let mut in_type = false;
+    let mut previous: Option<TokenKind> = None;
+
+    for token in tokens {
+        // declarations start at the beginning of a line, and anything
+        // indented belongs to the declaration before it
+        if token.position.column == 0 {
+            in_type = token.kind == TokenKind::LowerName("type");
+
+            if let TokenKind::LowerName(name) = token.kind {
+                if !KEYWORDS.contains(&name) {
+                    out.insert(name);
+                }
+            }
+        }
+
+        match (previous, token.kind) {
+            // `port sendMessage : String -> Cmd msg`
+            (Some(TokenKind::LowerName("port")), TokenKind::LowerName(name))
+                if !KEYWORDS.contains(&name) =>
+            {
+                out.insert(name);
+            }
+
+            // `type Msg = Click | Hover`, or `type alias Model = ...`
+            (
+                Some(TokenKind::LowerName("type"))
+                | Some(TokenKind::LowerName("alias"))
+                | Some(TokenKind::Symbol("="))
+                | Some(TokenKind::Symbol("|")),
+                TokenKind::UpperName(name),
+            ) if in_type => {
+                out.insert(name);
+            }
+
+            _ => (),
+        }
+
+        previous = Some(token.kind);
+    }
+
+    out
+}
+
+fn add_unqualified(header: &mut Header, name: &str, written: &str, position: Position) {
+    for import in header.imports.iter_mut() {
+        let exposed = match &import.exposing {
+            Some(Exposing::Explicit(exposed)) => exposed.iter().any(|item| item.name() == name),
+            Some(Exposing::Everything) | None => false,
+        };
+
+        if exposed {
+            import.references.push(Reference {
+                name: name.to_string(),
+                written: written.to_string(),
+                position,
+            });
+        }
+    }
+}
+
+/// Skip a parenthesized (or braced, or whatever) group, starting at the
+/// opening symbol.
+fn skip_balanced(tokens: &mut Peekable<Lexer>, open: &str, close: &str) {
+    match tokens.peek() {
+        Some(Token {
+            kind: TokenKind::Symbol(symbol),
+            ..
+        }) if *symbol == open => {
+            tokens.next();
+            skip_balanced_rest(tokens, open, close);
+        }
+        _ => (),
+    }
+}
+
+/// Skip the rest of a group whose opening symbol we've already consumed.
+fn skip_balanced_rest(tokens: &mut Peekable<Lexer>, open: &str, close: &str) {
+    let mut depth = 1;
+
+    for token in tokens {
+        match token.kind {
+            TokenKind::Symbol(symbol) if symbol == open => depth += 1,
+            TokenKind::Symbol(symbol) if symbol == close => {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use crate::importfinder::{self, ElmJson, FoundImport};
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+#[derive(Debug)]
+pub struct Commit {
+    pub id: String,
+
+    /// When the commit was made, in ISO 8601 format
+    pub date: String,
+}
+
+/// Reads files out of a git repository's history. We never check anything
+/// out, so this is safe to run in a working copy with uncommitted changes.
+pub struct Repo {
+    root: PathBuf,
+
+    /// We read every file through a single `git cat-file --batch` process,
+    /// since starting a new git process for each file would be really slow.
+    cat_file: Child,
+    cat_file_in: ChildStdin,
+    cat_file_out: BufReader<ChildStdout>,
+}
+
+impl Repo {
+    /// Open the repository containing `path`.
+    pub fn containing(path: &Path) -> Result<Repo> {
+        let root = git(path, &["rev-parse", "--show-toplevel"])
+            .with_context(|| format!("could not find a git repository at {}", path.display()))?;
+        let root = PathBuf::from(root.trim())
+            .canonicalize()
+            .context("could not make an absolute path to the git repository")?;
+
+        let mut cat_file = Command::new("git")
+            .arg("-C")
+            .arg(&root)
+            .args(["cat-file", "--batch"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("could not start `git cat-file`")?;
+
+        let cat_file_in = cat_file
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("could not write to `git cat-file`"))?;
+        let cat_file_out = BufReader::new(
+            cat_file
+                .stdout
+                .take()
+                .ok_or_else(|| anyhow!("could not read from `git cat-file`"))?,
+        );
+
+        Ok(Repo {
+            root,
+            cat_file,
+            cat_file_in,
+            cat_file_out,
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The commits leading up to `rev`, newest first. We only follow first
+    /// parents so that work on merged branches doesn't show up twice.
+    pub fn commits(&self, rev: &str) -> Result<Vec<Commit>> {
+        let log = git(
+            &self.root,
+            &["log", "--first-parent", "--format=%H %cI", rev],
+        )
+        .with_context(|| format!("could not list the commits leading up to {}", rev))?;
+
+        Ok(log
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .map(|(id, date)| Commit {
+                id: id.to_string(),
+                date: date.to_string(),
+            })
+            .collect())
+    }
+
+    /// The contents of a file (relative to the repository root) as of a
+    /// commit, or `None` if it didn't exist then.
+    pub fn read(&mut self, commit: &str, path: &Path) -> Result<Option<Vec<u8>>> {
+        writeln!(self.cat_file_in, "{}:{}", commit, path.display())
+            .and_then(|_| self.cat_file_in.flush())
+            .context("could not write to `git cat-file`")?;
+
+        let mut header = String::new();
+        self.cat_file_out
+            .read_line(&mut header)
+            .context("could not read from `git cat-file`")?;
+
+        // the header is `OBJECT missing` or `ID TYPE SIZE`
+        if header.trim_end().ends_with(" missing") {
+            return Ok(None);
+        }
+
+        let mut parts = header.trim_end().rsplitn(3, ' ');
+        let (size, kind) = match (parts.next(), parts.next()) {
+            (Some(size), Some(kind)) => (
+                size.parse::<usize>().with_context(|| {
+                    format!("could not read the size in `{}`", header.trim_end())
+                })?,
+                kind,
+            ),
+            _ => bail!(
+                "got an unexpected response from `git cat-file`: {}",
+                header.trim_end()
+            ),
+        };
+
+        // the contents are followed by a newline, which we don't want
+        let mut contents = vec![0; size + 1];
+        self.cat_file_out
+            .read_exact(&mut contents)
+            .context("could not read from `git cat-file`")?;
+        contents.pop();
+
+        if kind == "blob" {
+            Ok(Some(contents))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// All the Elm files in a directory (relative to the repository root) as
+    /// of a commit.
+    pub fn elm_files(&self, commit: &str, directory: &Path) -> Result<Vec<PathBuf>> {
+        let directory = if directory.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            directory
+        };
+
+        let listing = git(
+            &self.root,
+            &[
+                "ls-tree",
+                "-r",
+                "-z",
+                "--name-only",
+                commit,
+                "--",
+                &directory.to_string_lossy(),
+            ],
+        )
+        .with_context(|| format!("could not list the files in {}", directory.display()))?;
+
+        Ok(listing
+            .split('\0')
+            .filter(|name| name.ends_with(".elm"))
+            .map(PathBuf::from)
+            .collect())
+    }
+}
+
+impl Drop for Repo {
+    fn drop(&mut self) {
+        // we don't care if this fails; we're done with it either way
+        let _ = self.cat_file.kill();
+        let _ = self.cat_file.wait();
+    }
+}
+
+/// Find the imports in the project roots (absolute paths inside the
+/// repository) as they were at a commit. This is the same as
+/// `ImportFinder::find`, just reading from git instead of the file system.
+pub fn find_at(
+    repo: &mut Repo,
+    commit: &str,
+    roots: &BTreeSet<PathBuf>,
+    scan_bodies: bool,
+) -> Result<BTreeMap<String, BTreeSet<FoundImport>>> {
+    let mut source_directories: BTreeMap<PathBuf, BTreeSet<PathBuf>> = BTreeMap::new();
+    let mut exposed_files = BTreeSet::new();
+
+    for root in roots {
+        let relative_root = root.strip_prefix(repo.root()).with_context(|| {
+            format!(
+                "the project root {} is not in the git repository at {}",
+                root.display(),
+                repo.root().display()
+            )
+        })?;
+
+        // the project might not have existed yet
+        let elm_json_path = relative_root.join("elm.json");
+        let elm_json = match repo.read(commit, &elm_json_path)? {
+            Some(source) => ElmJson::from_slice(&source, &elm_json_path)?,
+            None => continue,
+        };
+
+        for dir in elm_json.source_directories() {
+            source_directories
+                .entry(normalize(&root.join(dir)))
+                .or_default()
+                .insert(root.to_owned());
+        }
+
+        exposed_files.extend(elm_json.exposed_files(root));
+    }
+
+    let mut files = BTreeSet::new();
+    for dir in source_directories.keys() {
+        if let Ok(relative_dir) = dir.strip_prefix(repo.root()) {
+            for file in repo.elm_files(commit, relative_dir)? {
+                files.insert(repo.root().join(file));
+            }
+        }
+    }
+
+    let mut out: BTreeMap<String, BTreeSet<FoundImport>> = BTreeMap::new();
+
+    for path in files {
+        let relative_path = path.strip_prefix(repo.root())?.to_path_buf();
+
+        let source_bytes = match repo.read(commit, &relative_path)? {
+            Some(source_bytes) => source_bytes,
+            None => continue,
+        };
+        let source = std::str::from_utf8(&source_bytes)
+            .with_context(|| format!("could not read {} as utf8", relative_path.display()))?;
+
+        for found_import in importfinder::found_imports(
+            &path,
+            source,
+            scan_bodies,
+            importfinder::roots_containing(&source_directories, &path),
+            exposed_files.contains(&path),
+        ) {
+            out.entry(found_import.import.to_string())
+                .or_default()
+                .insert(found_import);
+        }
+    }
+
+    Ok(out)
+}
+
+/// Resolve `.` and `..` in a path without touching the file system (the
+/// directories might not exist anymore!)
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other.as_os_str()),
+        }
+    }
+
+    out
+}
+
+/// The Elm files in the repository containing `dir` that were changed,
+/// added, or deleted since `rev`, as absolute paths. We compare against the
+/// point where `HEAD` branched off from `rev`, so anything that happened on
+/// `rev` after that doesn't count. This compares against the working copy,
+/// so uncommitted (and untracked) files count too.
+pub fn changed_files(dir: &Path, rev: &str) -> Result<BTreeSet<PathBuf>> {
+    let root = git(dir, &["rev-parse", "--show-toplevel"])
+        .with_context(|| format!("could not find a git repository at {}", dir.display()))?;
+    let root = PathBuf::from(root.trim())
+        .canonicalize()
+        .context("could not make an absolute path to the git repository")?;
+
+    let merge_base = git(&root, &["merge-base", rev, "HEAD"])
+        .with_context(|| format!("could not find where HEAD branched off from {}", rev))?;
+    let merge_base = merge_base.trim();
+
+    let changed = git(
+        &root,
+        &["diff", "--name-only", "--no-renames", "-z", merge_base],
+    )
+    .with_context(|| format!("could not list the files changed since {}", rev))?;
+    let untracked = git(&root, &["ls-files", "--others", "--exclude-standard", "-z"])
+        .context("could not list untracked files")?;
+
+    Ok(changed
+        .split('\0')
+        .chain(untracked.split('\0'))
+        .filter(|name| name.ends_with(".elm"))
+        .map(|name| root.join(name))
+        .collect())
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("could not run git")?;
+
+    if !output.status.success() {
+        bail!(
+            "`git {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    String::from_utf8(output.stdout).context("git's output was not utf8")
+}
@@ -1,41 +1,135 @@
+use crate::header::{self, Exposing, Reference};
 use anyhow::{bail, Context, Result};
 use crossbeam::channel;
-use lazy_static::lazy_static;
-use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::cell::{OnceCell, RefCell};
 use std::collections::{BTreeMap, BTreeSet};
+use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
 
+#[derive(Debug)]
 pub struct ImportFinder {
     roots: BTreeSet<PathBuf>,
+    scan_bodies: bool,
+
+    // these only depend on the roots' elm.json files, which modules exist,
+    // and the package cache, so we work them out once per finder. Keep the
+    // finder around to avoid doing it again (say, in the language server.)
+    source_directories: OnceCell<BTreeMap<PathBuf, BTreeSet<PathBuf>>>,
+    exposed_files: OnceCell<BTreeSet<PathBuf>>,
+    modules: OnceCell<BTreeSet<String>>,
+    package_modules: RefCell<BTreeMap<String, BTreeSet<String>>>,
 }
 
 impl ImportFinder {
     pub fn new(roots: BTreeSet<PathBuf>) -> ImportFinder {
-        ImportFinder { roots }
+        ImportFinder {
+            roots,
+            scan_bodies: false,
+            source_directories: OnceCell::new(),
+            exposed_files: OnceCell::new(),
+            modules: OnceCell::new(),
+            package_modules: RefCell::new(BTreeMap::new()),
+        }
     }
 
-    fn source_directories(&self) -> Result<BTreeSet<PathBuf>> {
-        let mut out = BTreeSet::new();
+    /// Look through module bodies (not just headers) for references to
+    /// imported values.
+    pub fn scan_bodies(mut self, scan_bodies: bool) -> ImportFinder {
+        self.scan_bodies = scan_bodies;
+        self
+    }
+
+    /// Every source directory, along with the roots that use it. These are
+    /// canonicalized so that roots sharing a directory (like `../shared/src`)
+    /// only get it scanned once.
+    fn source_directories(&self) -> Result<&BTreeMap<PathBuf, BTreeSet<PathBuf>>> {
+        if let Some(source_directories) = self.source_directories.get() {
+            return Ok(source_directories);
+        }
+
+        let mut out: BTreeMap<PathBuf, BTreeSet<PathBuf>> = BTreeMap::new();
 
         for root in self.roots.iter() {
-            let source = fs::read(root.join("elm.json"))?;
-            let elm_json: ElmJson = serde_json::from_slice(&source)?;
+            for dir in ElmJson::from_root(root)?.source_directories() {
+                let dir = root.join(dir);
+                let canonical = dir.canonicalize().with_context(|| {
+                    format!("could not find the source directory {}", dir.display())
+                })?;
 
-            for dir in elm_json.source_directories {
-                out.insert(root.join(dir));
+                out.entry(canonical).or_default().insert(root.to_owned());
             }
         }
 
+        Ok(self.source_directories.get_or_init(|| out))
+    }
+
+    /// The files for every module that a package root exposes.
+    fn exposed_files(&self) -> Result<&BTreeSet<PathBuf>> {
+        if let Some(exposed_files) = self.exposed_files.get() {
+            return Ok(exposed_files);
+        }
+
+        let mut out = BTreeSet::new();
+
+        for root in self.roots.iter() {
+            out.extend(ElmJson::from_root(root)?.exposed_files(root));
+        }
+
+        Ok(self.exposed_files.get_or_init(|| out))
+    }
+
+    /// The modules a package (like `elm-community/list-extra`) exposes, in
+    /// whatever versions the roots depend on. We read these from the
+    /// compiler's package cache, so the roots need to have been built at
+    /// least once.
+    pub fn package_modules(&self, package: &str) -> Result<BTreeSet<String>> {
+        if let Some(modules) = self.package_modules.borrow().get(package) {
+            return Ok(modules.clone());
+        }
+
+        let mut out = BTreeSet::new();
+        let elm_home = elm_home()?;
+
+        for root in self.roots.iter() {
+            let path = match ElmJson::from_root(root)?.package_directory(&elm_home, package)? {
+                Some(directory) => directory.join("elm.json"),
+                None => continue,
+            };
+
+            let source = fs::read(&path).with_context(|| {
+                format!(
+                    "could not read {}. Does the project in {} build?",
+                    path.display(),
+                    root.display()
+                )
+            })?;
+            let package_elm_json: PackageElmJson = serde_json::from_slice(&source)
+                .with_context(|| format!("could not parse {}", path.display()))?;
+
+            out.extend(package_elm_json.exposed_modules.modules());
+        }
+
+        self.package_modules
+            .borrow_mut()
+            .insert(package.to_string(), out.clone());
+
         Ok(out)
     }
 
-    fn builder(&self) -> Result<ignore::WalkBuilder> {
-        let source_directories = self
-            .source_directories()
-            .context("could not get the source directories for project roots")?;
-        let mut source_directories = source_directories.iter();
+    fn builder(
+        &self,
+        source_directories: &BTreeMap<PathBuf, BTreeSet<PathBuf>>,
+    ) -> Result<ignore::WalkBuilder> {
+        // if one source directory is inside another, walking the outer one
+        // already gets us all the files in the inner one.
+        let mut source_directories = source_directories.keys().filter(|dir| {
+            !source_directories
+                .keys()
+                .any(|other| other != *dir && dir.starts_with(other))
+        });
 
         let first_source_directory = match source_directories.next() {
             None => {
@@ -65,13 +159,155 @@ impl ImportFinder {
         Ok(types)
     }
 
+    /// The names of all the modules in the source directories, worked out
+    /// from their paths (so `src/Page/Home.elm` is `Page.Home`.)
+    pub fn modules(&self) -> Result<&BTreeSet<String>> {
+        if let Some(modules) = self.modules.get() {
+            return Ok(modules);
+        }
+
+        let mut out = BTreeSet::new();
+
+        for source_directory in self.source_directories()?.keys() {
+            for path in self.elm_files(source_directory)? {
+                if let Ok(relative) = path.strip_prefix(source_directory) {
+                    out.insert(
+                        relative
+                            .with_extension("")
+                            .iter()
+                            .map(|segment| segment.to_string_lossy())
+                            .collect::<Vec<_>>()
+                            .join("."),
+                    );
+                }
+            }
+        }
+
+        Ok(self.modules.get_or_init(|| out))
+    }
+
+    /// Every Elm file in the source directories, grouped by the project
+    /// roots they belong to.
+    pub fn files_by_root(&self) -> Result<BTreeMap<PathBuf, BTreeSet<PathBuf>>> {
+        let mut out: BTreeMap<PathBuf, BTreeSet<PathBuf>> = self
+            .roots
+            .iter()
+            .map(|root| (root.to_owned(), BTreeSet::new()))
+            .collect();
+
+        for (source_directory, roots) in self.source_directories()? {
+            let files = self.elm_files(source_directory)?;
+
+            for root in roots {
+                out.entry(root.to_owned())
+                    .or_default()
+                    .extend(files.iter().cloned());
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// All the Elm files in a single directory. Unlike `find`, this doesn't
+    /// read them.
+    fn elm_files(&self, directory: &Path) -> Result<Vec<PathBuf>> {
+        let mut out = Vec::new();
+
+        let mut builder = ignore::WalkBuilder::new(directory);
+        builder.standard_filters(true);
+        builder.types(self.elm_types()?);
+
+        for maybe_dir_entry in builder.build() {
+            let dir_entry = maybe_dir_entry.context("could not read an entry from a root")?;
+
+            if dir_entry.file_type().map(|ft| ft.is_dir()).unwrap_or(true) {
+                continue;
+            }
+
+            out.push(dir_entry.into_path());
+        }
+
+        Ok(out)
+    }
+
+    /// Like `find`, but only look at some files (absolute paths) instead of
+    /// walking the roots. Anything that isn't an Elm file in one of the
+    /// roots' source directories (or doesn't exist anymore) gets skipped.
+    pub fn find_in(
+        &self,
+        files: &BTreeSet<PathBuf>,
+    ) -> Result<BTreeMap<String, BTreeSet<FoundImport>>> {
+        let mut sources = BTreeMap::new();
+
+        for path in files {
+            if path.extension().and_then(|ext| ext.to_str()) != Some("elm") || !path.is_file() {
+                continue;
+            }
+
+            sources.insert(
+                path.to_owned(),
+                fs::read_to_string(path)
+                    .with_context(|| format!("could not read {}", path.display()))?,
+            );
+        }
+
+        self.find_in_sources(&sources)
+    }
+
+    /// Like `find_in`, but with the source for each file already in hand
+    /// (say, from an editor buffer that hasn't been saved yet.)
+    pub fn find_in_sources(
+        &self,
+        sources: &BTreeMap<PathBuf, String>,
+    ) -> Result<BTreeMap<String, BTreeSet<FoundImport>>> {
+        let mut out: BTreeMap<String, BTreeSet<FoundImport>> = BTreeMap::new();
+
+        let source_directories = self
+            .source_directories()
+            .context("could not get the source directories for project roots")?;
+        let exposed_files = self
+            .exposed_files()
+            .context("could not get the exposed modules for package roots")?;
+
+        for (path, source) in sources {
+            let roots = roots_containing(source_directories, path);
+            if roots.is_empty() {
+                continue;
+            }
+
+            for found_import in found_imports(
+                path,
+                source,
+                self.scan_bodies,
+                roots,
+                exposed_files.contains(path),
+            ) {
+                out.entry(found_import.import.to_string())
+                    .or_default()
+                    .insert(found_import);
+            }
+        }
+
+        Ok(out)
+    }
+
     pub fn find(&self) -> Result<BTreeMap<String, BTreeSet<FoundImport>>> {
         let mut out: BTreeMap<String, BTreeSet<FoundImport>> = BTreeMap::new();
 
         let (parent_results_sender, results_receiver) = channel::unbounded();
         let (parent_error_sender, error_receiver) = channel::unbounded();
 
-        self.builder()?.build_parallel().run(|| {
+        let scan_bodies = self.scan_bodies;
+        let source_directories = self
+            .source_directories()
+            .context("could not get the source directories for project roots")?;
+        let source_directories = &source_directories;
+        let exposed_files = self
+            .exposed_files()
+            .context("could not get the exposed modules for package roots")?;
+        let exposed_files = &exposed_files;
+
+        self.builder(source_directories)?.build_parallel().run(|| {
             let results_sender = parent_results_sender.clone();
             let error_sender = parent_error_sender.clone();
 
@@ -109,41 +345,18 @@ impl ImportFinder {
                     }
                 };
 
-                lazy_static! {
-                    static ref IMPORT_RE: Regex =
-                        Regex::new(r"^import +([A-Z][A-Za-z0-9_\.]*)").unwrap();
-                }
-
-                // perf idea; keep track of if we've finished the import list and
-                // bail on any further lines once we get there. Since imports
-                // are forbidden after the block at the top of the module, we
-                // shouldn't miss anything by skipping the rest of the lines
-                // in each file!
-                let mut seen_an_import = false;
-
-                for (line_number, line) in source.lines().enumerate() {
-                    match IMPORT_RE.captures(line).and_then(|m| m.get(1)) {
-                        Some(import_module) => {
-                            seen_an_import = true;
-
-                            if let Err(err) = results_sender.send(FoundImport {
-                                path: dir_entry.path().to_path_buf(),
-                                import: import_module.as_str().to_string(),
-                                position: Position {
-                                    row: line_number + 1,
-                                    column: import_module.start(),
-                                },
-                            }) {
-                                error_sender.send(err.into()).unwrap();
-                                return ignore::WalkState::Quit;
-                            }
-                        }
-
-                        None => {
-                            if seen_an_import {
-                                return ignore::WalkState::Continue;
-                            }
-                        }
+                let found_imports = found_imports(
+                    dir_entry.path(),
+                    source,
+                    scan_bodies,
+                    roots_containing(source_directories, dir_entry.path()),
+                    exposed_files.contains(dir_entry.path()),
+                );
+
+                for found_import in found_imports {
+                    if let Err(err) = results_sender.send(found_import) {
+                        error_sender.send(err.into()).unwrap();
+                        return ignore::WalkState::Quit;
                     }
                 }
 
@@ -180,11 +393,70 @@ impl ImportFinder {
     }
 }
 
+/// Read the imports out of a single file's source.
+pub fn found_imports(
+    path: &Path,
+    source: &str,
+    scan_bodies: bool,
+    roots: BTreeSet<PathBuf>,
+    in_exposed_module: bool,
+) -> Vec<FoundImport> {
+    let header = if scan_bodies {
+        header::parse_with_references(source)
+    } else {
+        header::parse(source)
+    };
+
+    let importing_module = header.module;
+
+    header
+        .imports
+        .into_iter()
+        .map(|import| FoundImport {
+            path: path.to_path_buf(),
+            import: import.name,
+            alias: import.alias,
+            exposing: import.exposing,
+            position: import.position,
+            references: import.references,
+            importing_module: importing_module.clone(),
+            in_exposed_module,
+            roots: roots.clone(),
+        })
+        .collect()
+}
+
+/// Which roots a file belongs to, given the source directories for each.
+pub fn roots_containing(
+    source_directories: &BTreeMap<PathBuf, BTreeSet<PathBuf>>,
+    path: &Path,
+) -> BTreeSet<PathBuf> {
+    source_directories
+        .iter()
+        .filter(|(dir, _)| path.starts_with(dir))
+        .flat_map(|(_, roots)| roots.iter().cloned())
+        .collect()
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct FoundImport {
     pub import: String,
     pub path: PathBuf,
     pub position: Position,
+    pub alias: Option<String>,
+    pub exposing: Option<Exposing>,
+    pub references: Vec<Reference>,
+
+    /// The name of the module this import appears in
+    pub importing_module: Option<String>,
+
+    /// Whether the import appears in a module that a package root exposes
+    /// (meaning it's part of the package's public API)
+    pub in_exposed_module: bool,
+
+    /// The project roots this file belongs to. This is usually just one, but
+    /// roots can share source directories.
+    pub roots: BTreeSet<PathBuf>,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
@@ -193,8 +465,320 @@ pub struct Position {
     pub column: usize,
 }
 
+/// Find every directory with an `elm.json` under `path`. This skips anything
+/// ignored by `.gitignore` (and friends), hidden directories, and package
+/// caches (`elm-stuff`, `ELM_HOME`, and `~/.elm`), which are full of
+/// packages' elm.json files.
+pub fn discover_roots(path: &Path) -> Result<BTreeSet<PathBuf>> {
+    let mut out = BTreeSet::new();
+
+    let package_caches: Vec<PathBuf> = env::var_os("ELM_HOME")
+        .map(PathBuf::from)
+        .into_iter()
+        .chain(env::var_os("HOME").map(|home| PathBuf::from(home).join(".elm")))
+        .filter_map(|cache| cache.canonicalize().ok())
+        .collect();
+
+    let mut builder = ignore::WalkBuilder::new(path);
+    builder.standard_filters(true);
+    builder.require_git(false);
+    builder.filter_entry(move |dir_entry| {
+        if dir_entry.file_name() == "elm-stuff" {
+            return false;
+        }
+
+        // only canonicalize when the name matches, since this runs for
+        // every file we walk past
+        !package_caches.iter().any(|cache| {
+            cache.file_name() == Some(dir_entry.file_name())
+                && dir_entry.path().canonicalize().ok().as_ref() == Some(cache)
+        })
+    });
+
+    for maybe_dir_entry in builder.build() {
+        let dir_entry =
+            maybe_dir_entry.context("could not read an entry while looking for roots")?;
+
+        if dir_entry.file_name() != "elm.json"
+            || dir_entry.file_type().map(|ft| ft.is_dir()).unwrap_or(true)
+        {
+            continue;
+        }
+
+        if let Some(root) = dir_entry.path().parent() {
+            out.insert(root.to_path_buf());
+        }
+    }
+
+    Ok(out)
+}
+
+/// A project's elm.json. Applications say where their source lives and pin
+/// exact dependency versions; packages always keep their source in `src` and
+/// give version ranges instead.
 #[derive(Debug, Deserialize)]
-struct ElmJson {
-    #[serde(rename = "source-directories")]
-    source_directories: Vec<PathBuf>,
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ElmJson {
+    Application {
+        #[serde(rename = "source-directories")]
+        source_directories: Vec<PathBuf>,
+
+        #[serde(rename = "elm-version")]
+        elm_version: String,
+
+        #[serde(default)]
+        dependencies: Dependencies,
+    },
+    Package {
+        #[serde(rename = "exposed-modules")]
+        exposed_modules: ExposedModules,
+
+        #[serde(rename = "elm-version")]
+        elm_version: String,
+
+        #[serde(default)]
+        dependencies: BTreeMap<String, String>,
+    },
+}
+
+impl ElmJson {
+    pub fn from_root(root: &Path) -> Result<ElmJson> {
+        let path = root.join("elm.json");
+
+        let source =
+            fs::read(&path).with_context(|| format!("could not read {}", path.display()))?;
+
+        Self::from_slice(&source, &path)
+    }
+
+    pub fn from_slice(source: &[u8], path: &Path) -> Result<ElmJson> {
+        serde_json::from_slice(source)
+            .with_context(|| format!("could not parse {}", path.display()))
+    }
+
+    pub fn source_directories(&self) -> Vec<PathBuf> {
+        match self {
+            ElmJson::Application {
+                source_directories, ..
+            } => source_directories.clone(),
+            ElmJson::Package { .. } => vec![PathBuf::from("src")],
+        }
+    }
+
+    pub fn exposed_modules(&self) -> Vec<String> {
+        match self {
+            ElmJson::Application { .. } => Vec::new(),
+            ElmJson::Package {
+                exposed_modules, ..
+            } => exposed_modules.modules(),
+        }
+    }
+
+    /// The files for the modules this package exposes, if `root` is where
+    /// its elm.json lives.
+    pub fn exposed_files(&self, root: &Path) -> Vec<PathBuf> {
+        self.exposed_modules()
+            .iter()
+            .map(|module| {
+                let mut path = root.join("src");
+                path.extend(module.split('.'));
+                path.set_extension("elm");
+                path
+            })
+            .collect()
+    }
+
+    /// Where a dependency lives in the package cache, if this project
+    /// depends on it. Packages only give version ranges, so we use the newest
+    /// installed version that fits.
+    fn package_directory(&self, elm_home: &Path, package: &str) -> Result<Option<PathBuf>> {
+        match self {
+            ElmJson::Application {
+                elm_version,
+                dependencies,
+                ..
+            } => {
+                let version = match dependencies
+                    .direct
+                    .get(package)
+                    .or_else(|| dependencies.indirect.get(package))
+                {
+                    Some(version) => version,
+                    None => return Ok(None),
+                };
+
+                Ok(Some(
+                    elm_home
+                        .join(elm_version)
+                        .join("packages")
+                        .join(package)
+                        .join(version),
+                ))
+            }
+
+            ElmJson::Package {
+                elm_version,
+                dependencies,
+                ..
+            } => {
+                let constraint = match dependencies.get(package) {
+                    Some(constraint) => constraint,
+                    None => return Ok(None),
+                };
+
+                let compiler = match newest_matching(elm_home, elm_version)? {
+                    Some(compiler) => compiler,
+                    None => bail!(
+                        "could not find a compiler version matching `{}` in {}",
+                        elm_version,
+                        elm_home.display()
+                    ),
+                };
+
+                let packages = compiler.join("packages").join(package);
+
+                match newest_matching(&packages, constraint)? {
+                    Some(directory) => Ok(Some(directory)),
+                    None => bail!(
+                        "could not find a version of {} matching `{}` in {}",
+                        package,
+                        constraint,
+                        packages.display()
+                    ),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Dependencies {
+    #[serde(default)]
+    direct: BTreeMap<String, String>,
+
+    #[serde(default)]
+    indirect: BTreeMap<String, String>,
+}
+
+/// The bits of a package's elm.json we care about, as found in `ELM_HOME`.
+#[derive(Debug, Deserialize)]
+struct PackageElmJson {
+    #[serde(rename = "exposed-modules")]
+    exposed_modules: ExposedModules,
+}
+
+/// Packages can either list their exposed modules or group them into
+/// categories for the docs.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ExposedModules {
+    List(Vec<String>),
+    Categorized(BTreeMap<String, Vec<String>>),
+}
+
+impl ExposedModules {
+    fn modules(&self) -> Vec<String> {
+        match self {
+            ExposedModules::List(modules) => modules.clone(),
+            ExposedModules::Categorized(categories) => {
+                categories.values().flatten().cloned().collect()
+            }
+        }
+    }
+}
+
+/// Where the Elm compiler keeps downloaded packages: `$ELM_HOME`, or
+/// `~/.elm` if that's not set.
+fn elm_home() -> Result<PathBuf> {
+    if let Some(elm_home) = env::var_os("ELM_HOME") {
+        return Ok(PathBuf::from(elm_home));
+    }
+
+    match env::var_os("HOME") {
+        Some(home) => Ok(PathBuf::from(home).join(".elm")),
+        None => bail!("could not find the Elm home directory. Try setting ELM_HOME."),
+    }
+}
+
+/// Find the subdirectory with the highest version number (like `1.0.5`)
+/// which satisfies an elm.json constraint (like `1.0.0 <= v < 2.0.0`.)
+fn newest_matching(directory: &Path, constraint: &str) -> Result<Option<PathBuf>> {
+    let mut newest: Option<(Version, PathBuf)> = None;
+
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err).with_context(|| format!("could not read {}", directory.display()))
+        }
+    };
+
+    for entry in entries {
+        let entry = entry.with_context(|| format!("could not read {}", directory.display()))?;
+
+        let version = match entry.file_name().to_str().and_then(Version::parse) {
+            Some(version) => version,
+            None => continue,
+        };
+
+        if !version.satisfies(constraint) {
+            continue;
+        }
+
+        if newest
+            .as_ref()
+            .map(|(best, _)| version > *best)
+            .unwrap_or(true)
+        {
+            newest = Some((version, entry.path()));
+        }
+    }
+
+    Ok(newest.map(|(_, path)| path))
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Version(u64, u64, u64);
+
+impl Version {
+    fn parse(version: &str) -> Option<Version> {
+        let mut parts = version.split('.').map(|part| part.parse().ok());
+
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(Some(major)), Some(Some(minor)), Some(Some(patch)), None) => {
+                Some(Version(major, minor, patch))
+            }
+            _ => None,
+        }
+    }
+
+    /// Constraints always look like `LOWER <= v < UPPER`, where either
+    /// comparison could be `<` or `<=`.
+    fn satisfies(&self, constraint: &str) -> bool {
+        let parts: Vec<&str> = constraint.split_whitespace().collect();
+
+        let (lower, lower_op, upper_op, upper) = match parts.as_slice() {
+            [lower, lower_op, "v", upper_op, upper] => (
+                Version::parse(lower),
+                *lower_op,
+                *upper_op,
+                Version::parse(upper),
+            ),
+            _ => return false,
+        };
+
+        let above = match (lower, lower_op) {
+            (Some(lower), "<=") => lower <= *self,
+            (Some(lower), "<") => lower < *self,
+            _ => false,
+        };
+
+        let below = match (upper, upper_op) {
+            (Some(upper), "<=") => *self <= upper,
+            (Some(upper), "<") => *self < upper,
+            _ => false,
+        };
+
+        above && below
+    }
 }
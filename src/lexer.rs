@@ -0,0 +1,261 @@
+use crate::importfinder::Position;
+
+/// A (very) small tokenizer for Elm source. It knows just enough to skip
+/// over whitespace, comments, and literals so that whatever reads the tokens
+/// only ever sees the names and symbols the compiler would see.
+pub struct Lexer<'a> {
+    source: &'a str,
+    offset: usize,
+    row: usize,
+    column: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenKind<'a> {
+    /// A capitalized name, possibly qualified: `Html` or `Html.Attributes`
+    UpperName(&'a str),
+
+    /// A lowercase name, possibly qualified: `text`, `import`, or `Html.text`
+    LowerName(&'a str),
+
+    /// Punctuation and operators: `(`, `,`, `..`, `|>`
+    Symbol(&'a str),
+
+    /// Strings, characters, and numbers. We never care about their contents,
+    /// just that we don't mistake them for code.
+    Literal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Token<'a> {
+    pub kind: TokenKind<'a>,
+    pub position: Position,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str) -> Lexer<'a> {
+        Lexer {
+            source,
+            offset: 0,
+            row: 1,
+            column: 0,
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.offset..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        self.rest().chars().nth(1)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let next = self.peek()?;
+
+        self.offset += next.len_utf8();
+        if next == '\n' {
+            self.row += 1;
+            self.column = 0;
+        } else {
+            self.column += 1;
+        }
+
+        Some(next)
+    }
+
+    fn bump_while(&mut self, predicate: impl Fn(char) -> bool) {
+        while let Some(next) = self.peek() {
+            if !predicate(next) {
+                break;
+            }
+            self.bump();
+        }
+    }
+
+    fn skip_whitespace_and_comments(&mut self) {
+        loop {
+            if self.peek().map(char::is_whitespace).unwrap_or(false) {
+                self.bump();
+            } else if self.rest().starts_with("--") {
+                self.bump_while(|c| c != '\n');
+            } else if self.rest().starts_with("{-") {
+                self.skip_block_comment();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Block comments (including `{-| doc comments -}`) nest in Elm, so we
+    /// have to keep track of how deep we are.
+    fn skip_block_comment(&mut self) {
+        let mut depth = 0;
+
+        while !self.rest().is_empty() {
+            if self.rest().starts_with("{-") {
+                depth += 1;
+                self.bump();
+                self.bump();
+            } else if self.rest().starts_with("-}") {
+                depth -= 1;
+                self.bump();
+                self.bump();
+
+                if depth == 0 {
+                    break;
+                }
+            } else {
+                self.bump();
+            }
+        }
+    }
+
+    fn name(&mut self) -> TokenKind<'a> {
+        let start = self.offset;
+        let mut last_segment_start = start;
+
+        self.bump_while(is_name_char);
+
+        // qualified names continue through dots, but only as long as the
+        // segments are capitalized. `model.field` is a record access, not a
+        // qualified name!
+        while self.source[last_segment_start..].starts_with(char::is_uppercase)
+            && self.peek() == Some('.')
+            && self.peek_second().map(char::is_alphabetic).unwrap_or(false)
+        {
+            self.bump();
+            last_segment_start = self.offset;
+            self.bump_while(is_name_char);
+        }
+
+        let name = &self.source[start..self.offset];
+
+        if self.source[last_segment_start..].starts_with(char::is_uppercase) {
+            TokenKind::UpperName(name)
+        } else {
+            TokenKind::LowerName(name)
+        }
+    }
+
+    fn string(&mut self) {
+        let terminator = if self.rest().starts_with("\"\"\"") {
+            "\"\"\""
+        } else {
+            "\""
+        };
+
+        for _ in 0..terminator.len() {
+            self.bump();
+        }
+
+        while !self.rest().is_empty() {
+            if self.rest().starts_with(terminator) {
+                for _ in 0..terminator.len() {
+                    self.bump();
+                }
+                break;
+            }
+
+            // single-quoted strings can't span lines, so if we see a newline
+            // the string was never closed. Bail so we don't eat the rest of
+            // the module.
+            if terminator == "\"" && self.peek() == Some('\n') {
+                break;
+            }
+
+            if self.bump() == Some('\\') {
+                self.bump();
+            }
+        }
+    }
+
+    fn char(&mut self) {
+        self.bump();
+
+        while let Some(next) = self.bump() {
+            match next {
+                '\\' => {
+                    self.bump();
+                }
+                '\'' | '\n' => break,
+                _ => {}
+            }
+        }
+    }
+
+    fn number(&mut self) {
+        self.bump_while(|c| c.is_ascii_alphanumeric());
+
+        if self.peek() == Some('.')
+            && self
+                .peek_second()
+                .map(|c| c.is_ascii_digit())
+                .unwrap_or(false)
+        {
+            self.bump();
+            self.bump_while(|c| c.is_ascii_alphanumeric());
+        }
+    }
+
+    fn glsl(&mut self) {
+        while !self.rest().is_empty() && !self.rest().starts_with("|]") {
+            self.bump();
+        }
+
+        self.bump();
+        self.bump();
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        self.skip_whitespace_and_comments();
+
+        let position = Position {
+            row: self.row,
+            column: self.column,
+        };
+        let start = self.offset;
+
+        let next = self.peek()?;
+
+        let kind = if next.is_alphabetic() || next == '_' {
+            self.name()
+        } else if next == '"' {
+            self.string();
+            TokenKind::Literal
+        } else if next == '\'' {
+            self.char();
+            TokenKind::Literal
+        } else if next.is_ascii_digit() {
+            self.number();
+            TokenKind::Literal
+        } else if self.rest().starts_with("[glsl|") {
+            self.glsl();
+            TokenKind::Literal
+        } else if is_operator_char(next) {
+            self.bump_while(is_operator_char);
+            TokenKind::Symbol(&self.source[start..self.offset])
+        } else {
+            self.bump();
+            TokenKind::Symbol(&self.source[start..self.offset])
+        };
+
+        Some(Token { kind, position })
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn is_operator_char(c: char) -> bool {
+    "+-*/=<>!&|^%:.$?@#~\\".contains(c)
+}
@@ -0,0 +1,553 @@
+use crate::store::{CheckResult, Scope, Severity, Store};
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Speak the Language Server Protocol over stdin and stdout, publishing
+/// diagnostics for forbidden imports in open documents. Returns the exit
+/// code the client asked for.
+pub fn serve(config_path: &Path) -> Result<i32> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+
+    let stdout = io::stdout();
+    let mut server = Server::new(config_path.to_owned(), stdout.lock())?;
+
+    while let Some(message) = read_message(&mut input)? {
+        if let Some(exit_code) = server.handle(message)? {
+            return Ok(exit_code);
+        }
+    }
+
+    // the client went away without telling us to exit
+    Ok(1)
+}
+
+struct Server<W: Write> {
+    config_path: PathBuf,
+    store: Store,
+
+    /// When we last loaded the config, so we can notice when it changes even
+    /// if the client doesn't tell us.
+    config_modified: Option<SystemTime>,
+
+    /// The text of every open document, by URI
+    documents: BTreeMap<String, String>,
+
+    shutting_down: bool,
+    out: W,
+}
+
+impl<W: Write> Server<W> {
+    fn new(config_path: PathBuf, out: W) -> Result<Server<W>> {
+        let store = Store::from_file_or_empty(&config_path)
+            .with_context(|| format!("could not load the config at {}", config_path.display()))?;
+
+        Ok(Server {
+            config_modified: modified(&config_path),
+            config_path,
+            store,
+            documents: BTreeMap::new(),
+            shutting_down: false,
+            out,
+        })
+    }
+
+    /// Handle a single message from the client. If it's time to stop, this
+    /// returns the exit code.
+    fn handle(&mut self, message: Value) -> Result<Option<i32>> {
+        let id = message.get("id").cloned();
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+        match (message.get("method").and_then(Value::as_str), id) {
+            (Some("initialize"), Some(id)) => {
+                self.respond(
+                    id,
+                    json!({
+                        "capabilities": {
+                            "textDocumentSync": {
+                                "openClose": true,
+                                "change": 1,
+                                "save": { "includeText": true },
+                            },
+                            "codeActionProvider": { "codeActionKinds": ["quickfix"] },
+                        },
+                        "serverInfo": {
+                            "name": "elm-forbid-import",
+                            "version": env!("CARGO_PKG_VERSION"),
+                        },
+                    }),
+                )?;
+
+                if params
+                    .pointer("/capabilities/workspace/didChangeWatchedFiles/dynamicRegistration")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false)
+                {
+                    self.watch_files()?;
+                }
+            }
+
+            (Some("shutdown"), Some(id)) => {
+                self.shutting_down = true;
+                self.respond(id, Value::Null)?;
+            }
+
+            (Some("exit"), _) => return Ok(Some(if self.shutting_down { 0 } else { 1 })),
+
+            (Some("textDocument/didOpen"), None) => {
+                if let (Some(uri), Some(text)) = (
+                    params.pointer("/textDocument/uri").and_then(Value::as_str),
+                    params.pointer("/textDocument/text").and_then(Value::as_str),
+                ) {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                    self.publish(uri)?;
+                }
+            }
+
+            (Some("textDocument/didChange"), None) => {
+                // we ask for full documents, so the last change has
+                // everything we need
+                if let (Some(uri), Some(text)) = (
+                    params.pointer("/textDocument/uri").and_then(Value::as_str),
+                    params
+                        .get("contentChanges")
+                        .and_then(Value::as_array)
+                        .and_then(|changes| changes.last())
+                        .and_then(|change| change.get("text"))
+                        .and_then(Value::as_str),
+                ) {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                    self.publish(uri)?;
+                }
+            }
+
+            (Some("textDocument/didSave"), None) => {
+                if let Some(uri) = params.pointer("/textDocument/uri").and_then(Value::as_str) {
+                    if let Some(text) = params.get("text").and_then(Value::as_str) {
+                        self.documents.insert(uri.to_string(), text.to_string());
+                    }
+                    self.publish(uri)?;
+                }
+            }
+
+            (Some("textDocument/didClose"), None) => {
+                if let Some(uri) = params.pointer("/textDocument/uri").and_then(Value::as_str) {
+                    self.documents.remove(uri);
+                    self.send_diagnostics(uri, Vec::new())?;
+                }
+            }
+
+            (Some("textDocument/codeAction"), Some(id)) => {
+                let actions = match params.pointer("/textDocument/uri").and_then(Value::as_str) {
+                    Some(uri) => self.code_actions(
+                        uri,
+                        params
+                            .pointer("/range/start/line")
+                            .and_then(Value::as_u64)
+                            .unwrap_or(0),
+                        params
+                            .pointer("/range/end/line")
+                            .and_then(Value::as_u64)
+                            .unwrap_or(u64::MAX),
+                    )?,
+                    None => Vec::new(),
+                };
+
+                self.respond(id, Value::Array(actions))?;
+            }
+
+            (Some("workspace/didChangeWatchedFiles"), None) => {
+                // a fresh store forgets the roots and modules we found before
+                self.reload()?;
+
+                let uris: Vec<String> = self.documents.keys().cloned().collect();
+                for uri in uris {
+                    self.publish(&uri)?;
+                }
+            }
+
+            (Some(method), Some(id)) => self.send(json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {
+                    "code": -32601,
+                    "message": format!("I don't know how to handle {}", method),
+                },
+            }))?,
+
+            // notifications we don't care about, and responses to our own
+            // requests
+            _ => (),
+        }
+
+        Ok(None)
+    }
+
+    /// Ask the client to tell us when the config changes, or anything that
+    /// would change which projects and modules there are: elm.json files, and
+    /// Elm files being created or deleted (but not edited, since that doesn't
+    /// matter until they're open.)
+    fn watch_files(&mut self) -> Result<()> {
+        let file_name = self
+            .config_path
+            .file_name()
+            .ok_or_else(|| anyhow!("the config path does not have a file name"))?
+            .to_string_lossy()
+            .to_string();
+
+        self.send(json!({
+            "jsonrpc": "2.0",
+            "id": "watch-files",
+            "method": "client/registerCapability",
+            "params": {
+                "registrations": [{
+                    "id": "watch-files",
+                    "method": "workspace/didChangeWatchedFiles",
+                    "registerOptions": {
+                        "watchers": [
+                            { "globPattern": format!("**/{}", file_name) },
+                            { "globPattern": "**/elm.json" },
+                            // created (1) or deleted (4)
+                            { "globPattern": "**/*.elm", "kind": 5 },
+                        ],
+                    },
+                }],
+            },
+        }))
+    }
+
+    fn reload(&mut self) -> Result<()> {
+        match Store::from_file_or_empty(&self.config_path) {
+            Ok(store) => {
+                self.store = store;
+                self.config_modified = modified(&self.config_path);
+                Ok(())
+            }
+
+            // the config is probably being edited, so keep using the old one
+            // until it makes sense again.
+            Err(err) => self.log(&format!("could not reload the config: {:?}", err)),
+        }
+    }
+
+    fn publish(&mut self, uri: &str) -> Result<()> {
+        if modified(&self.config_path) != self.config_modified {
+            self.reload()?;
+        }
+
+        match self.diagnostics(uri) {
+            Ok(diagnostics) => self.send_diagnostics(uri, diagnostics),
+            Err(err) => self.log(&format!("could not check {}: {:?}", uri, err)),
+        }
+    }
+
+    /// Offer to swap in the replacement for any forbidden imports that have
+    /// one on the given lines.
+    fn code_actions(&mut self, uri: &str, start_line: u64, end_line: u64) -> Result<Vec<Value>> {
+        let diagnostics = match self.diagnostics(uri) {
+            Ok(diagnostics) => diagnostics,
+            Err(err) => {
+                self.log(&format!("could not check {}: {:?}", uri, err))?;
+                return Ok(Vec::new());
+            }
+        };
+
+        Ok(diagnostics
+            .into_iter()
+            .filter_map(|diagnostic| {
+                let line = diagnostic.pointer("/range/start/line")?.as_u64()?;
+                if line < start_line || line > end_line {
+                    return None;
+                }
+
+                let replacement = diagnostic.pointer("/data/replacement")?.as_str()?;
+                let new_text = diagnostic.pointer("/data/newText")?.as_str()?;
+
+                let mut changes = serde_json::Map::new();
+                changes.insert(
+                    uri.to_string(),
+                    json!([{ "range": diagnostic["range"], "newText": new_text }]),
+                );
+
+                Some(json!({
+                    "title": format!("Import {} instead", replacement),
+                    "kind": "quickfix",
+                    "isPreferred": true,
+                    "diagnostics": [diagnostic],
+                    "edit": { "changes": changes },
+                }))
+            })
+            .collect())
+    }
+
+    fn diagnostics(&mut self, uri: &str) -> Result<Vec<Value>> {
+        let source = match self.documents.get(uri) {
+            Some(source) => source.to_owned(),
+            None => return Ok(Vec::new()),
+        };
+
+        let path = path_from_uri(uri)?;
+        let path = path.canonicalize().unwrap_or(path);
+
+        let absolute_config_path = std::env::current_dir()
+            .context("could not get the current working directory")?
+            .join(&self.config_path);
+        let config_uri = uri_from_path(
+            &absolute_config_path
+                .canonicalize()
+                .unwrap_or(absolute_config_path),
+        );
+        let config_source = fs::read_to_string(&self.config_path).unwrap_or_default();
+
+        let results = self.store.check(&Scope::Buffer {
+            path,
+            source: source.to_owned(),
+        })?;
+
+        Ok(results
+            .iter()
+            // removing an import is good news, not something to fix!
+            .filter(|result| !result.error_is_in_config())
+            .filter_map(|result| diagnostic(result, &source, &config_uri, &config_source))
+            .collect())
+    }
+
+    fn send_diagnostics(&mut self, uri: &str, diagnostics: Vec<Value>) -> Result<()> {
+        self.send(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        }))
+    }
+
+    fn log(&mut self, message: &str) -> Result<()> {
+        self.send(json!({
+            "jsonrpc": "2.0",
+            "method": "window/logMessage",
+            "params": { "type": 1, "message": message },
+        }))
+    }
+
+    fn respond(&mut self, id: Value, result: Value) -> Result<()> {
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+    }
+
+    fn send(&mut self, message: Value) -> Result<()> {
+        let body = serde_json::to_string(&message).context("could not serialize a message")?;
+
+        write!(self.out, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+            .and_then(|_| self.out.flush())
+            .context("could not write a message")
+    }
+}
+
+/// Turn a check result into an LSP diagnostic. The hint goes in the message,
+/// and the rule's metadata becomes related information pointing at the
+/// rule in the config. We also stash the replacement (if any) in `data` so
+/// we can offer it as a code action.
+fn diagnostic(
+    result: &CheckResult,
+    source: &str,
+    config_uri: &str,
+    config_source: &str,
+) -> Option<Value> {
+    let position = result.position()?;
+    let line = source
+        .lines()
+        .nth(position.row.saturating_sub(1))
+        .unwrap_or("");
+    let line_number = position.row.saturating_sub(1);
+
+    let rule_location = json!({
+        "uri": config_uri,
+        "range": range(
+            rule_line(config_source, result.rule().unwrap_or_else(|| result.import())),
+            0,
+            0,
+        ),
+    });
+
+    let metadata = result.metadata();
+    let related: Vec<Value> = [
+        ("owner", metadata.owner),
+        ("ticket", metadata.ticket),
+        ("reason", metadata.reason),
+    ]
+    .iter()
+    .filter_map(|(label, value)| {
+        value.map(|value| {
+            json!({
+                "location": rule_location,
+                "message": format!("{}: {}", label, value),
+            })
+        })
+    })
+    .collect();
+
+    let mut out = json!({
+        "range": {
+            "start": {
+                "line": line_number,
+                "character": utf16_column(line, position.column),
+            },
+            "end": {
+                "line": line_number,
+                "character": utf16_column(line, position.column + result.written().chars().count()),
+            },
+        },
+        "severity": match result.severity() {
+            Severity::Error => 1,
+            Severity::Warn => 2,
+            Severity::Info => 3,
+        },
+        "code": result.import(),
+        "source": "elm-forbid-import",
+        "message": result.message(),
+    });
+
+    if !related.is_empty() {
+        out["relatedInformation"] = Value::Array(related);
+    }
+
+    // we can only swap the module name in the import itself. Without an
+    // alias, references in the body are qualified with the old name, so we
+    // keep that working by aliasing the replacement to it.
+    if let Some(replacement) = result.suggested_replacement() {
+        if !result.is_reference() {
+            let new_text = match result.alias() {
+                Some(_) => replacement.to_string(),
+                None => format!("{} as {}", replacement, result.import()),
+            };
+
+            out["data"] = json!({ "replacement": replacement, "newText": new_text });
+        }
+    }
+
+    Some(out)
+}
+
+fn range(line: usize, start: usize, end: usize) -> Value {
+    json!({
+        "start": { "line": line, "character": start },
+        "end": { "line": line, "character": end },
+    })
+}
+
+/// Which line of the config has the table for a forbidden import rule (by
+/// its name in the config, which might be a pattern), or the first line if we
+/// can't find it.
+fn rule_line(config_source: &str, name: &str) -> usize {
+    let bare = format!("[forbidden.{}]", name);
+    let quoted = format!("[forbidden.\"{}\"]", name);
+
+    config_source
+        .lines()
+        .position(|line| line.trim() == bare || line.trim() == quoted)
+        .unwrap_or(0)
+}
+
+/// LSP positions count UTF-16 code units, but ours count characters.
+fn utf16_column(line: &str, column: usize) -> usize {
+    line.chars().take(column).map(|c| c.len_utf16()).sum()
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn path_from_uri(uri: &str) -> Result<PathBuf> {
+    let encoded = uri
+        .strip_prefix("file://")
+        .ok_or_else(|| anyhow!("I can only check files, not {}", uri))?;
+
+    let mut bytes = Vec::new();
+    let mut rest = encoded.as_bytes();
+
+    while let Some((first, tail)) = rest.split_first() {
+        match (first, tail.get(0..2)) {
+            (b'%', Some(hex)) => {
+                let byte = std::str::from_utf8(hex)
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| anyhow!("{} has a bad percent-encoding", uri))?;
+
+                bytes.push(byte);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(*first);
+                rest = tail;
+            }
+        }
+    }
+
+    Ok(PathBuf::from(
+        String::from_utf8(bytes).with_context(|| format!("{} is not utf8", uri))?,
+    ))
+}
+
+fn uri_from_path(path: &Path) -> String {
+    let mut out = String::from("file://");
+
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    out
+}
+
+/// Read a single message (a `Content-Length` header, then that many bytes of
+/// JSON.) Returns `None` when the input ends.
+fn read_message(input: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut content_length = None;
+
+    loop {
+        let mut header = String::new();
+        if input
+            .read_line(&mut header)
+            .context("could not read a header")?
+            == 0
+        {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = Some(
+                    value
+                        .trim()
+                        .parse::<usize>()
+                        .with_context(|| format!("could not read the length in `{}`", header))?,
+                );
+            }
+        }
+    }
+
+    let content_length =
+        content_length.ok_or_else(|| anyhow!("got a message without a Content-Length"))?;
+
+    let mut body = vec![0; content_length];
+    input
+        .read_exact(&mut body)
+        .context("could not read a message")?;
+
+    serde_json::from_slice(&body)
+        .context("could not parse a message")
+        .map(Some)
+}
@@ -1,10 +1,18 @@
 use anyhow::{anyhow, Context, Result};
+use chrono::NaiveDate;
 use clap::Clap;
+use std::io::Read;
 use std::path::PathBuf;
 use std::process;
-use store::Store;
+use store::{ForbidOptions, Scope, Severity, Store};
 
+mod fix;
+mod header;
+mod history;
 mod importfinder;
+mod lexer;
+mod lsp;
+mod pattern;
 mod store;
 
 #[derive(Debug, Clap)]
@@ -34,7 +42,9 @@ struct Options {
 enum Mode {
     /// Forbid a new import
     Forbid {
-        /// The fully-qualified name to forbid (e.g. `Html.Events`)
+        /// The fully-qualified name to forbid (e.g. `Html.Events`), or a
+        /// pattern where `*` matches anything (e.g. `Html.*` or
+        /// `Nri.Ui.Doodad.V*`)
         name: String,
 
         /// An additional string to print when showing an error for this
@@ -42,10 +52,79 @@ enum Mode {
         /// another approach? Give up and buy a farm?)
         #[clap(short, long)]
         hint: Option<String>,
+
+        /// A module that can be imported instead without any other changes
+        /// (e.g. `Html.Styled`.) Editors can offer to swap it in. Only for
+        /// exact module names, not patterns.
+        #[clap(long)]
+        replacement: Option<String>,
+
+        /// Who to ask about this import (a person or a team)
+        #[clap(long)]
+        owner: Option<String>,
+
+        /// A link to wherever the migration away from this import is tracked
+        #[clap(long)]
+        ticket: Option<String>,
+
+        /// Why this import is forbidden
+        #[clap(long)]
+        reason: Option<String>,
+
+        /// Instead of forbidding the whole module, only forbid importing it
+        /// with this name exposed (e.g. `style` or `(|.)`.) Can be given
+        /// multiple times.
+        #[clap(long)]
+        exposing: Vec<String>,
+
+        /// Instead of forbidding the whole module, only forbid importing it
+        /// with `exposing (..)`
+        #[clap(long)]
+        exposing_everything: bool,
+
+        /// Also look for references in module bodies (e.g. `Attr.style` or
+        /// `style`), not just imports. This is slower, since we have to read
+        /// the whole file instead of stopping after the imports.
+        #[clap(long)]
+        scan_body: bool,
+
+        /// Keep track of how many times each file uses this import (counting
+        /// references in the module body) and fail `check` if the count goes
+        /// up, instead of letting allowed files use it as much as they like.
+        #[clap(long)]
+        ratchet: bool,
+
+        /// Only forbid this import in files matching this glob (relative to
+        /// the config file, e.g. `src/Api/**`.) Can be given multiple times.
+        #[clap(long)]
+        include: Vec<String>,
+
+        /// Allow this import in files matching this glob (relative to the
+        /// config file, e.g. `src/Internal/**`.) Can be given multiple times.
+        #[clap(long)]
+        exclude: Vec<String>,
+
+        /// Only forbid this import in this project root (as relative to the
+        /// working directory.) Can be given multiple times.
+        #[clap(long = "root")]
+        roots: Vec<PathBuf>,
+
+        /// How seriously to take new usages: `error`, `warn`, or `info`. Only
+        /// errors make `check` fail.
+        #[clap(long, default_value = "error")]
+        severity: Severity,
+
+        /// When all usages should be gone by (e.g. `2027-01-01`.) `check`
+        /// warns as this gets close, and once it's past, existing usages are
+        /// errors too.
+        #[clap(long)]
+        deadline: Option<NaiveDate>,
     },
 
-    /// Forbid a list of imports held in a CSV. The file should be a 2-column
-    /// CSV with "module" and "hint" fields and no headers.
+    /// Forbid a list of imports held in a CSV. The file should have "module"
+    /// and "hint" columns and no headers, optionally followed by "owner",
+    /// "ticket", and "reason" columns. Modules that are already forbidden
+    /// only get those updated, and keep the rest of their settings.
     ForbidFromCsv {
         /// What file has the forbidden import list?
         path: PathBuf,
@@ -57,12 +136,87 @@ enum Mode {
         name: String,
     },
 
+    /// Forbid importing any module from a package (e.g.
+    /// `elm-community/list-extra`.) Module names come from the package's
+    /// elm.json in the Elm home directory (`ELM_HOME`, or `~/.elm`.)
+    ForbidPackage {
+        /// The package to forbid, as `author/name`
+        name: String,
+
+        /// An additional string to print when showing an error for this
+        /// package.
+        #[clap(short, long)]
+        hint: Option<String>,
+    },
+
+    /// Stop forbidding a package
+    UnforbidPackage {
+        /// The package to stop forbidding, as `author/name`
+        name: String,
+    },
+
+    /// Declare an architectural layer: a group of modules which may only
+    /// import modules from certain other layers. Running this again for the
+    /// same layer replaces its modules and allowed layers.
+    AddLayer {
+        /// What to call the layer (e.g. `Page`)
+        name: String,
+
+        /// A module name or pattern for the modules in this layer (e.g.
+        /// `Page.*`.) Can be given multiple times.
+        #[clap(long = "module", required = true)]
+        modules: Vec<String>,
+
+        /// Another layer modules in this layer may import. Can be given
+        /// multiple times.
+        #[clap(long)]
+        may_import: Vec<String>,
+    },
+
+    /// Remove an architectural layer.
+    RemoveLayer {
+        /// The name of the layer to remove
+        name: String,
+    },
+
+    /// Only allow importing modules matching these names or patterns (plus
+    /// the project's own modules.) Once anything is allowed, importing
+    /// anything else is forbidden.
+    Allow {
+        /// Fully-qualified names (e.g. `Html.Styled`) or patterns where `*`
+        /// matches anything (e.g. `Html.Styled.*`)
+        #[clap(required = true)]
+        names: Vec<String>,
+    },
+
+    /// Remove modules from the allow list.
+    Unallow {
+        /// The names or patterns to remove, exactly as they were allowed
+        #[clap(required = true)]
+        names: Vec<String>,
+    },
+
     /// Add a project root (a directory containing `elm.json`) to check for imports.
     AddRoot {
         // The path to the project, as relative to the working directory.
         path: PathBuf,
     },
 
+    /// Find every Elm project (that is, every `elm.json`) under a directory
+    /// and add them as project roots. This respects `.gitignore` and skips
+    /// `elm-stuff`.
+    DiscoverRoots {
+        /// Where to start looking, as relative to the working directory.
+        #[clap(default_value = ".")]
+        path: PathBuf,
+
+        /// Instead of adding the projects we find now, look under this
+        /// directory again every time we scan. (Use `remove-root` with the
+        /// same path to stop.)
+        #[clap(long)]
+        auto: bool,
+    },
+
     /// Remove a project root from checking.
     RemoveRoot {
         // The path to the project, as relative to the working directory.
@@ -73,14 +227,73 @@ enum Mode {
     Update,
 
     /// Check what imports still need to be cleaned up
-    Check,
+    Check {
+        /// Only check Elm files that changed since HEAD branched off from this
+        /// git ref (including uncommitted changes.) Deleted files still get
+        /// reported if the config mentions them.
+        #[clap(long)]
+        since: Option<String>,
+
+        /// Only check these Elm files (say, the ones staged for a commit.)
+        /// This only reports new forbidden imports, since we can't tell
+        /// whether imports were removed without looking at everything else.
+        #[clap(conflicts_with = "since")]
+        files: Vec<PathBuf>,
+
+        /// Read the source for a single file from stdin instead of from disk
+        /// (say, an editor buffer that hasn't been saved yet.) Like when
+        /// passing files, this only reports new forbidden imports.
+        #[clap(long, requires = "stdin-filename", conflicts_with_all = &["since", "files"])]
+        stdin: bool,
+
+        /// Where the source from `--stdin` would live, so we know which
+        /// usages to compare it against
+        #[clap(long)]
+        stdin_filename: Option<PathBuf>,
+    },
+
+    /// Show how far along we are in removing each forbidden import: how many
+    /// files are grandfathered, how many places use it, and how many files
+    /// in each root still do.
+    #[clap(alias = "stats")]
+    Report,
+
+    /// Swap forbidden imports for their replacements (see `forbid
+    /// --replacement`), keeping aliases and exposing lists. Run `update`
+    /// afterwards to stop allowing the imports you got rid of.
+    Fix {
+        /// Print a diff of what would change instead of changing anything
+        #[clap(long)]
+        dry_run: bool,
+    },
+
+    /// Run a language server over stdin and stdout, so editors can show
+    /// forbidden imports as you type.
+    Lsp,
+
+    /// Show how usages of each forbidden import changed over time by scanning
+    /// the git history (as CSV, or JSON with `--format json`.) This reads
+    /// straight from git, so it won't touch your working copy.
+    History {
+        /// Where to start looking back from
+        #[clap(long, default_value = "HEAD")]
+        rev: String,
+
+        /// Only look at every Nth commit, counting back from `--rev`
+        #[clap(long, default_value = "1")]
+        every: usize,
+
+        /// Look at this many commits at most
+        #[clap(long)]
+        limit: Option<usize>,
+    },
 }
 
 #[derive(Debug, PartialEq)]
 enum Format {
     Human,
     Editor,
-    JSON,
+    Json,
 }
 
 impl std::str::FromStr for Format {
@@ -90,7 +303,7 @@ impl std::str::FromStr for Format {
         match input {
             "human" => Ok(Format::Human),
             "editor" => Ok(Format::Editor),
-            "json" => Ok(Format::JSON),
+            "json" => Ok(Format::Json),
             _ => Err(BadFormat {}),
         }
     }
@@ -99,9 +312,9 @@ impl std::str::FromStr for Format {
 #[derive(Debug)]
 struct BadFormat {}
 
-impl ToString for BadFormat {
-    fn to_string(&self) -> String {
-        String::from("bad format")
+impl std::fmt::Display for BadFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "bad format")
     }
 }
 
@@ -126,8 +339,44 @@ fn run(opts: Options) -> Result<i32> {
     })?;
 
     match opts.mode {
-        Mode::Forbid { name, hint } => {
-            store.forbid(name, hint);
+        Mode::Forbid {
+            name,
+            hint,
+            replacement,
+            owner,
+            ticket,
+            reason,
+            exposing,
+            exposing_everything,
+            scan_body,
+            ratchet,
+            include,
+            exclude,
+            roots,
+            severity,
+            deadline,
+        } => {
+            store
+                .forbid(
+                    name,
+                    ForbidOptions {
+                        hint,
+                        replacement,
+                        owner,
+                        ticket,
+                        reason,
+                        exposing,
+                        exposing_everything,
+                        scan_body,
+                        ratchet,
+                        include,
+                        exclude,
+                        roots,
+                        severity,
+                        deadline,
+                    },
+                )
+                .context("could not forbid the import")?;
             store.write().context("could not update the config file")?;
 
             Ok(0)
@@ -152,8 +401,25 @@ fn run(opts: Options) -> Result<i32> {
                         "I need a module name in the first column of the CSV at "
                     ))?;
                 let hint = record.get(1).map(|name| name.to_string());
-
-                store.forbid(module, hint);
+                let optional = |index| {
+                    record
+                        .get(index)
+                        .filter(|value| !value.is_empty())
+                        .map(|value| value.to_string())
+                };
+
+                store
+                    .forbid_from_csv(
+                        module,
+                        ForbidOptions {
+                            hint,
+                            owner: optional(2),
+                            ticket: optional(3),
+                            reason: optional(4),
+                            ..ForbidOptions::default()
+                        },
+                    )
+                    .context("could not forbid the import")?;
             }
 
             store.write().context("could not update the config file")?;
@@ -168,6 +434,56 @@ fn run(opts: Options) -> Result<i32> {
             Ok(0)
         }
 
+        Mode::ForbidPackage { name, hint } => {
+            store
+                .forbid_package(name, hint)
+                .context("could not forbid the package")?;
+            store.write().context("could not update the config file")?;
+
+            Ok(0)
+        }
+
+        Mode::UnforbidPackage { name } => {
+            store.unforbid_package(name);
+            store.write().context("could not update the config file")?;
+
+            Ok(0)
+        }
+
+        Mode::AddLayer {
+            name,
+            modules,
+            may_import,
+        } => {
+            store.add_layer(name, modules, may_import);
+            store.write().context("could not update the config file")?;
+
+            Ok(0)
+        }
+
+        Mode::RemoveLayer { name } => {
+            store.remove_layer(name);
+            store.write().context("could not update the config file")?;
+
+            Ok(0)
+        }
+
+        Mode::Allow { names } => {
+            store
+                .allow(names)
+                .context("could not add to the allow list")?;
+            store.write().context("could not update the config file")?;
+
+            Ok(0)
+        }
+
+        Mode::Unallow { names } => {
+            store.unallow(names);
+            store.write().context("could not update the config file")?;
+
+            Ok(0)
+        }
+
         Mode::AddRoot { path } => {
             store
                 .add_root(path)
@@ -177,6 +493,25 @@ fn run(opts: Options) -> Result<i32> {
             Ok(0)
         }
 
+        Mode::DiscoverRoots { path, auto } => {
+            if auto {
+                store
+                    .add_auto_roots(path)
+                    .context("could not add the directory to search for roots")?;
+            } else {
+                let found = store
+                    .discover_roots(path)
+                    .context("could not discover project roots")?;
+
+                for root in found {
+                    println!("added {}", root.display());
+                }
+            }
+            store.write().context("could not update the config file")?;
+
+            Ok(0)
+        }
+
         Mode::RemoveRoot { path } => {
             store
                 .remove_root(path)
@@ -195,19 +530,59 @@ fn run(opts: Options) -> Result<i32> {
             Ok(0)
         }
 
-        Mode::Check => {
-            let results = store
-                .check()
+        Mode::Check {
+            since,
+            files,
+            stdin,
+            stdin_filename,
+        } => {
+            let current_dir =
+                std::env::current_dir().context("could not get the current working directory")?;
+            let absolute = |file: &PathBuf| {
+                file.canonicalize()
+                    .unwrap_or_else(|_| current_dir.join(file))
+            };
+
+            let scope = match (since, stdin_filename) {
+                (Some(rev), _) => {
+                    Scope::Files(store.changed_since(&rev).with_context(|| {
+                        format!("could not find the files changed since {}", rev)
+                    })?)
+                }
+                (None, Some(path)) if stdin => {
+                    let mut source = String::new();
+                    std::io::stdin()
+                        .read_to_string(&mut source)
+                        .context("could not read the source from stdin")?;
+
+                    Scope::Buffer {
+                        path: absolute(&path),
+                        source,
+                    }
+                }
+                (None, _) if !files.is_empty() => {
+                    Scope::Files(files.iter().map(absolute).collect())
+                }
+                (None, _) => Scope::Everything,
+            };
+
+            let mut results = store
+                .check(&scope)
                 .context("could not check for forbidden imports")?;
+            if !files.is_empty() || stdin {
+                results.retain(|result| !result.error_is_in_config());
+            }
+
+            let errors: Vec<_> = results.iter().filter(|item| item.is_error()).collect();
 
             match opts.format {
-                Format::JSON => {
+                Format::Json => {
                     println!(
                         "{}",
                         serde_json::to_string(&results)
                             .context("when formatting results as JSON")?
                     );
-                    if results.is_empty() {
+                    if errors.is_empty() {
                         Ok(0)
                     } else {
                         Ok(1)
@@ -215,7 +590,9 @@ fn run(opts: Options) -> Result<i32> {
                 }
                 _ => {
                     let all_in_config =
-                        !results.is_empty() && results.iter().all(|item| item.error_is_in_config());
+                        !errors.is_empty() && errors.iter().all(|item| item.error_is_in_config());
+                    let all_past_deadline =
+                        !errors.is_empty() && errors.iter().all(|item| item.is_past_deadline());
 
                     for result in &results {
                         println!("{}", result);
@@ -224,12 +601,14 @@ fn run(opts: Options) -> Result<i32> {
                     if opts.format == Format::Human {
                         if all_in_config {
                             println!( "\nIt looks like you removed some forbidden imports. Good job! To update the config\nand remove this error, just run me with the `update` command!" );
-                        } else if !results.is_empty() {
+                        } else if all_past_deadline {
+                            println!( "\nSome forbidden imports are past their deadlines, so running me with `update` won't\nhelp. Time to finish those migrations!" );
+                        } else if !errors.is_empty() {
                             println!( "\nIf these are too much to handle right now (or you intended to import a forbidden\nmodule), please run me with the `update` command!" );
                         }
                     }
 
-                    if !results.is_empty() {
+                    if !errors.is_empty() {
                         Ok(1)
                     } else {
                         Ok(0)
@@ -237,5 +616,72 @@ fn run(opts: Options) -> Result<i32> {
                 }
             }
         }
+
+        Mode::Report => {
+            let reports = store
+                .report()
+                .context("could not report on forbidden imports")?;
+
+            match opts.format {
+                Format::Json => println!(
+                    "{}",
+                    serde_json::to_string(&reports).context("when formatting reports as JSON")?
+                ),
+                _ => println!(
+                    "{}",
+                    reports
+                        .iter()
+                        .map(|report| report.to_string())
+                        .collect::<Vec<String>>()
+                        .join("\n\n")
+                ),
+            }
+
+            Ok(0)
+        }
+
+        Mode::Fix { dry_run } => {
+            let fixes = store.fix().context("could not fix forbidden imports")?;
+
+            for fix in fixes.iter() {
+                if dry_run {
+                    print!("{}", fix.unified_diff());
+                } else {
+                    std::fs::write(&fix.path, &fix.after)
+                        .with_context(|| format!("could not write {}", fix.path.display()))?;
+                    println!("fixed {}", fix.path.display());
+                }
+            }
+
+            Ok(0)
+        }
+
+        Mode::Lsp => lsp::serve(&opts.config_path).context("could not run the language server"),
+
+        Mode::History { rev, every, limit } => {
+            let history = store
+                .history(&rev, every, limit)
+                .context("could not look at the history of forbidden imports")?;
+
+            match opts.format {
+                Format::Json => println!(
+                    "{}",
+                    serde_json::to_string(&history).context("when formatting history as JSON")?
+                ),
+                _ => {
+                    let mut writer = csv::Writer::from_writer(std::io::stdout());
+
+                    for point in history.iter() {
+                        writer
+                            .serialize(point)
+                            .context("when formatting history as CSV")?;
+                    }
+
+                    writer.flush().context("could not write history")?;
+                }
+            }
+
+            Ok(0)
+        }
     }
 }
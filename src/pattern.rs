@@ -0,0 +1,99 @@
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use regex::Regex;
+use std::path::Path;
+
+/// A module name to match imports against. This is either an exact module
+/// name (`Html.Attributes`) or a glob where `*` stands for any run of
+/// characters, dots included (`Html.*` or `Nri.Ui.Doodad.V*`.)
+#[derive(Debug)]
+pub struct ModulePattern {
+    glob: Option<Regex>,
+    name: String,
+}
+
+impl ModulePattern {
+    /// Whether a name is a glob instead of an exact module name.
+    pub fn is_glob(pattern: &str) -> bool {
+        pattern.contains('*')
+    }
+
+    pub fn new(pattern: &str) -> Result<ModulePattern> {
+        let glob = if ModulePattern::is_glob(pattern) {
+            let source = pattern
+                .split('*')
+                .map(regex::escape)
+                .collect::<Vec<String>>()
+                .join(".*");
+
+            Some(
+                Regex::new(&format!("^{}$", source))
+                    .with_context(|| format!("could not compile the pattern `{}`", pattern))?,
+            )
+        } else {
+            None
+        };
+
+        Ok(ModulePattern {
+            glob,
+            name: pattern.to_string(),
+        })
+    }
+
+    pub fn matches(&self, module: &str) -> bool {
+        match &self.glob {
+            Some(glob) => glob.is_match(module),
+            None => self.name == module,
+        }
+    }
+}
+
+/// Which files a rule applies to. Paths are matched relative to the config
+/// file, so `src/Api/**` means the `src/Api` directory next to it.
+#[derive(Debug)]
+pub struct PathScope {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl PathScope {
+    pub fn new<'a>(
+        include: impl IntoIterator<Item = &'a String>,
+        exclude: impl IntoIterator<Item = &'a String>,
+    ) -> Result<PathScope> {
+        let include = glob_set(include)?;
+
+        Ok(PathScope {
+            include: if include.is_empty() {
+                None
+            } else {
+                Some(include)
+            },
+            exclude: glob_set(exclude)?,
+        })
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        let included = match &self.include {
+            Some(include) => include.is_match(path),
+            None => true,
+        };
+
+        included && !self.exclude.is_match(path)
+    }
+}
+
+fn glob_set<'a>(globs: impl IntoIterator<Item = &'a String>) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+
+    for glob in globs {
+        builder.add(
+            GlobBuilder::new(glob)
+                .literal_separator(true)
+                .build()
+                .with_context(|| format!("could not parse the glob `{}`", glob))?,
+        );
+    }
+
+    builder.build().context("could not build a set of globs")
+}
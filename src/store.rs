@@ -1,12 +1,19 @@
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{Local, NaiveDate};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::OnceCell;
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{self, Display};
 use std::fs;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use crate::fix::{self, Fix};
+use crate::header::{Exposing, Reference};
+use crate::history;
 use crate::importfinder;
+use crate::pattern::{ModulePattern, PathScope};
 
 static AUTOGEN_HEADER: &str = "# WARNING: this file is managed with `elm-forbid-imports`. Manual edits will\n# be overwritten!\n\n";
 
@@ -18,20 +25,823 @@ pub struct Store {
     #[serde(default, skip_serializing_if = "btreeset_is_empty")]
     roots: BTreeSet<PathBuf>,
 
+    /// Directories to search for project roots every time we scan, instead
+    /// of listing them in `roots`.
+    #[serde(
+        default,
+        rename = "auto-roots",
+        skip_serializing_if = "btreeset_is_empty"
+    )]
+    auto_roots: BTreeSet<PathBuf>,
+
     #[serde(default, skip_serializing_if = "forbidden_is_empty")]
     forbidden: BTreeMap<String, ForbiddenImport>,
+
+    #[serde(default, skip_serializing_if = "layers_is_empty")]
+    layers: BTreeMap<String, Layer>,
+
+    #[serde(default, skip_serializing_if = "AllowList::is_empty")]
+    allowed: AllowList,
+
+    #[serde(
+        default,
+        rename = "forbidden-packages",
+        skip_serializing_if = "forbidden_packages_is_empty"
+    )]
+    forbidden_packages: BTreeMap<String, ForbiddenPackage>,
+
+    /// Finding the roots and reading their elm.json files is slow enough that
+    /// we don't want to do it on every check when the language server runs
+    /// one per keystroke, so we keep the finder around until the roots (or
+    /// whether we scan bodies) change.
+    #[serde(skip)]
+    finder: OnceCell<importfinder::ImportFinder>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 struct ForbiddenImport {
     hint: Option<String>,
 
+    /// A module to import instead, if it's a drop-in replacement
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    replacement: Option<String>,
+
+    /// Who to ask about this (a person or a team)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    owner: Option<String>,
+
+    /// Where the migration away from this is tracked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ticket: Option<String>,
+
+    /// Why this is forbidden in the first place
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+
+    /// Only forbid imports which expose these names (like `style` or
+    /// `(|.)`) instead of forbidding the whole module.
+    #[serde(default, skip_serializing_if = "btreeset_is_empty")]
+    exposing: BTreeSet<String>,
+
+    /// Only forbid imports which expose everything with `exposing (..)`
+    #[serde(
+        default,
+        rename = "exposing-everything",
+        skip_serializing_if = "is_false"
+    )]
+    exposing_everything: bool,
+
+    /// Also look through module bodies for references to whatever this
+    /// forbids (like `Attr.style` or `style`), not just the imports
+    #[serde(default, rename = "scan-body", skip_serializing_if = "is_false")]
+    scan_body: bool,
+
+    /// Keep track of how many times each file uses this instead of just
+    /// whether it does, and complain when the number goes up.
+    #[serde(default, skip_serializing_if = "is_false")]
+    ratchet: bool,
+
+    /// Only forbid this in files matching these globs (relative to the
+    /// config file.)
+    #[serde(default, skip_serializing_if = "btreeset_is_empty")]
+    include: BTreeSet<String>,
+
+    /// Never forbid this in files matching these globs (relative to the
+    /// config file.)
+    #[serde(default, skip_serializing_if = "btreeset_is_empty")]
+    exclude: BTreeSet<String>,
+
+    /// Only forbid this in files belonging to these project roots (relative
+    /// to the config file.) If empty, forbid it everywhere.
+    #[serde(default, skip_serializing_if = "btreeset_is_empty")]
+    roots: BTreeSet<PathBuf>,
+
+    /// How seriously to take new usages. Only errors fail `check`.
+    #[serde(default, skip_serializing_if = "Severity::is_error")]
+    severity: Severity,
+
+    /// When all usages should be gone by. After this, grandfathered usages
+    /// are errors too.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deadline: Option<NaiveDate>,
+
+    #[serde(default, skip_serializing_if = "Usages::is_empty")]
+    usages: Usages,
+
+    /// Usages of modules more specific than the forbidden name itself. When
+    /// the name is a pattern (like `Html.*`) we keep track of usages per
+    /// concrete module it matched, so that a file importing `Html.Attributes`
+    /// doesn't get to start importing `Html.Events` too.
+    #[serde(
+        default,
+        rename = "matched-usages",
+        skip_serializing_if = "matched_usages_is_empty"
+    )]
+    matched_usages: BTreeMap<String, Usages>,
+
+    /// Usages of forbidden values, by module and then by name (like `style`
+    /// or `(|.)`.) `..` stands for `exposing (..)`. We keep the module and
+    /// the name apart since there's no telling where one ends and the other
+    /// begins in something like `Parser.(|.)`
+    #[serde(
+        default,
+        rename = "value-usages",
+        skip_serializing_if = "value_usages_is_empty"
+    )]
+    value_usages: BTreeMap<String, BTreeMap<String, Usages>>,
+}
+
+/// Group the places we found something by file (relative to the config.)
+fn sites_by_file<'a, 'b>(
+    sites: Option<&'b Vec<Site<'a>>>,
+    parent_path: &Path,
+) -> BTreeMap<PathBuf, Vec<&'b Site<'a>>> {
+    let mut out: BTreeMap<PathBuf, Vec<&Site>> = BTreeMap::new();
+
+    for site in sites.into_iter().flatten() {
+        if let Some(path) = pathdiff::diff_paths(&site.found.path, parent_path) {
+            out.entry(path).or_default().push(site);
+        }
+    }
+
+    out
+}
+
+/// Turn the places we found something into usages by file (relative to the
+/// config.)
+fn files_usages(sites: &[Site], parent_path: &Path) -> Usages {
+    Usages::Files(
+        sites
+            .iter()
+            .flat_map(|site| pathdiff::diff_paths(&site.found.path, parent_path))
+            .collect(),
+    )
+}
+
+/// How to judge the places we found something, compared to the places we've
+/// previously allowed it.
+struct Rule<'a> {
+    /// The forbidden name the rule is under in the config (like `Html.*`),
+    /// if it's a forbidden import and not a layer or something.
+    name: Option<&'a str>,
+    hint: Option<Cow<'a, str>>,
+    replacement: Option<&'a str>,
+    metadata: Metadata<'a>,
+    severity: Severity,
+    ratchet: bool,
+    deadline: DeadlineStatus,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warn,
+    #[default]
+    Error,
+}
+
+impl Severity {
+    fn is_error(&self) -> bool {
+        *self == Severity::Error
+    }
+
+    /// What to put in front of messages. Errors are the default, so they
+    /// don't get anything.
+    fn prefix(&self) -> &'static str {
+        match self {
+            Severity::Info => "info: ",
+            Severity::Warn => "warning: ",
+            Severity::Error => "",
+        }
+    }
+}
+
+impl std::str::FromStr for Severity {
+    type Err = BadSeverity;
+
+    fn from_str(input: &str) -> Result<Severity, BadSeverity> {
+        match input {
+            "info" => Ok(Severity::Info),
+            "warn" => Ok(Severity::Warn),
+            "error" => Ok(Severity::Error),
+            _ => Err(BadSeverity {}),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BadSeverity {}
+
+impl Display for BadSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bad severity (try `error`, `warn`, or `info`)")
+    }
+}
+
+/// Who to talk to about a rule, and where to find out more.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct Metadata<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<&'a str>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ticket: Option<&'a str>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<&'a str>,
+}
+
+impl Display for Metadata<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = vec![
+            self.reason.map(|reason| format!("reason: {}", reason)),
+            self.owner.map(|owner| format!("owner: {}", owner)),
+            self.ticket.map(|ticket| format!("ticket: {}", ticket)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        if parts.is_empty() {
+            Ok(())
+        } else {
+            write!(f, " [{}]", parts.join(", "))
+        }
+    }
+}
+
+/// How many days before a deadline we start warning about it.
+static DEADLINE_WARNING_DAYS: i64 = 30;
+
+#[derive(Debug, Clone, Copy)]
+enum DeadlineStatus {
+    None,
+    Approaching { deadline: NaiveDate, days_left: i64 },
+    Past { deadline: NaiveDate },
+}
+
+impl DeadlineStatus {
+    fn new(deadline: Option<NaiveDate>, today: NaiveDate) -> DeadlineStatus {
+        let deadline = match deadline {
+            Some(deadline) => deadline,
+            None => return DeadlineStatus::None,
+        };
+
+        let days_left = (deadline - today).num_days();
+
+        if days_left < 0 {
+            DeadlineStatus::Past { deadline }
+        } else if days_left <= DEADLINE_WARNING_DAYS {
+            DeadlineStatus::Approaching {
+                deadline,
+                days_left,
+            }
+        } else {
+            DeadlineStatus::None
+        }
+    }
+}
+
+/// The opposite of forbidding imports: once there's anything in here, the
+/// project may only import modules matching these patterns (or its own
+/// modules.)
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct AllowList {
     #[serde(default, skip_serializing_if = "btreeset_is_empty")]
-    usages: BTreeSet<PathBuf>,
+    modules: BTreeSet<String>,
+
+    /// Existing imports of modules outside the allow list, keyed by the
+    /// imported module.
+    #[serde(default, skip_serializing_if = "matched_usages_is_empty")]
+    usages: BTreeMap<String, Usages>,
+}
+
+impl AllowList {
+    fn is_empty(&self) -> bool {
+        self.modules.is_empty() && self.usages.is_empty()
+    }
+
+    /// Find all the imports of modules which are neither in the allow list
+    /// nor part of the project itself.
+    fn found_usages<'a>(
+        &self,
+        own_modules: &BTreeSet<String>,
+        imports_to_files: &'a BTreeMap<String, BTreeSet<importfinder::FoundImport>>,
+    ) -> Result<BTreeMap<Subject, Vec<Site<'a>>>> {
+        let mut out = BTreeMap::new();
+
+        if self.modules.is_empty() {
+            return Ok(out);
+        }
+
+        let mut patterns = Vec::new();
+        for module in self.modules.iter() {
+            patterns.push(ModulePattern::new(module)?);
+        }
+
+        for (import, found_imports) in imports_to_files.iter() {
+            if own_modules.contains(import)
+                || patterns.iter().any(|pattern| pattern.matches(import))
+            {
+                continue;
+            }
+
+            out.insert(
+                Subject::Module(import.to_string()),
+                found_imports
+                    .iter()
+                    .map(|found| Site {
+                        found,
+                        reference: None,
+                    })
+                    .collect(),
+            );
+        }
+
+        Ok(out)
+    }
+}
+
+/// A whole package (like `elm-community/list-extra`) whose modules may not be
+/// imported.
+#[derive(Debug, Deserialize, Serialize)]
+struct ForbiddenPackage {
+    hint: Option<String>,
+
+    /// Existing imports of the package's modules, keyed by module.
+    #[serde(default, skip_serializing_if = "matched_usages_is_empty")]
+    usages: BTreeMap<String, Usages>,
+}
+
+impl ForbiddenPackage {
+    fn found_usages<'a>(
+        &self,
+        modules: &BTreeSet<String>,
+        imports_to_files: &'a BTreeMap<String, BTreeSet<importfinder::FoundImport>>,
+    ) -> BTreeMap<Subject, Vec<Site<'a>>> {
+        imports_to_files
+            .iter()
+            .filter(|(import, _)| modules.contains(*import))
+            .map(|(import, found_imports)| {
+                (
+                    Subject::Module(import.to_string()),
+                    found_imports
+                        .iter()
+                        .map(|found| Site {
+                            found,
+                            reference: None,
+                        })
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+}
+
+/// An architectural layer: a group of modules which may only import modules
+/// from certain other layers. Modules which don't belong to any layer can
+/// import and be imported by anything.
+#[derive(Debug, Deserialize, Serialize)]
+struct Layer {
+    /// Patterns for the modules in this layer, like `Page.*`. If a module
+    /// matches more than one layer, it belongs to the first one
+    /// alphabetically.
+    modules: BTreeSet<String>,
+
+    /// The other layers modules in this layer may import.
+    #[serde(
+        default,
+        rename = "may-import",
+        skip_serializing_if = "btreeset_is_empty"
+    )]
+    may_import: BTreeSet<String>,
+
+    /// Existing imports which cross into a layer we may not import, keyed by
+    /// the imported module.
+    #[serde(default, skip_serializing_if = "matched_usages_is_empty")]
+    usages: BTreeMap<String, Usages>,
+}
+
+impl Layer {
+    /// Find all the imports from modules in this layer into layers it may not
+    /// import, grouped by the imported module.
+    fn found_usages<'a>(
+        &self,
+        name: &str,
+        layers: &LayerPatterns,
+        imports_to_files: &'a BTreeMap<String, BTreeSet<importfinder::FoundImport>>,
+    ) -> BTreeMap<Subject, Vec<Site<'a>>> {
+        let mut out: BTreeMap<Subject, Vec<Site>> = BTreeMap::new();
+
+        for (import, found_imports) in imports_to_files.iter() {
+            match layers.layer_of(import) {
+                Some(target) if target != name && !self.may_import.contains(target) => (),
+                _ => continue,
+            }
+
+            for found in found_imports {
+                let source = found
+                    .importing_module
+                    .as_ref()
+                    .and_then(|module| layers.layer_of(module));
+
+                if source == Some(name) {
+                    out.entry(Subject::Module(import.to_string()))
+                        .or_default()
+                        .push(Site {
+                            found,
+                            reference: None,
+                        });
+                }
+            }
+        }
+
+        out
+    }
+}
+
+/// The compiled module patterns for every layer, so we only have to build
+/// them once per check.
+struct LayerPatterns<'a> {
+    layers: Vec<(&'a str, Vec<ModulePattern>)>,
+}
+
+impl<'a> LayerPatterns<'a> {
+    fn new(layers: &'a BTreeMap<String, Layer>) -> Result<LayerPatterns<'a>> {
+        let mut out = Vec::new();
+
+        for (name, layer) in layers.iter() {
+            let mut patterns = Vec::new();
+            for module in layer.modules.iter() {
+                patterns.push(ModulePattern::new(module)?);
+            }
+
+            out.push((name.as_str(), patterns));
+        }
+
+        Ok(LayerPatterns { layers: out })
+    }
+
+    fn layer_of(&self, module: &str) -> Option<&'a str> {
+        self.layers
+            .iter()
+            .find(|(_, patterns)| patterns.iter().any(|pattern| pattern.matches(module)))
+            .map(|(name, _)| *name)
+    }
+}
+
+/// Everything you can say about an import when forbidding it, besides its name.
+#[derive(Debug, Default)]
+pub struct ForbidOptions {
+    pub hint: Option<String>,
+    pub replacement: Option<String>,
+    pub owner: Option<String>,
+    pub ticket: Option<String>,
+    pub reason: Option<String>,
+    pub exposing: Vec<String>,
+    pub exposing_everything: bool,
+    pub scan_body: bool,
+    pub ratchet: bool,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub roots: Vec<PathBuf>,
+    pub severity: Severity,
+    pub deadline: Option<NaiveDate>,
+}
+
+/// Where a forbidden thing is currently allowed. In ratchet mode we keep
+/// counts as well, so we can tell when a file starts using it more.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+enum Usages {
+    Files(BTreeSet<PathBuf>),
+    Counts(BTreeMap<PathBuf, usize>),
+}
+
+impl Default for Usages {
+    fn default() -> Usages {
+        Usages::Files(BTreeSet::new())
+    }
+}
+
+impl Usages {
+    fn is_empty(&self) -> bool {
+        match self {
+            Usages::Files(files) => files.is_empty(),
+            Usages::Counts(counts) => counts.is_empty(),
+        }
+    }
+
+    fn files(&self) -> BTreeSet<PathBuf> {
+        match self {
+            Usages::Files(files) => files.clone(),
+            Usages::Counts(counts) => counts.keys().cloned().collect(),
+        }
+    }
+
+    /// How many times a file is allowed to use this. We only know this in
+    /// ratchet mode.
+    fn count(&self, file: &Path) -> Option<usize> {
+        match self {
+            Usages::Files(_) => None,
+            Usages::Counts(counts) => counts.get(file).copied(),
+        }
+    }
+}
+
+impl ForbiddenImport {
+    fn usages_of(&self, name: &str, subject: &Subject) -> Option<&Usages> {
+        match subject.value() {
+            None if subject.module() == name => Some(&self.usages),
+            None => self.matched_usages.get(subject.module()),
+            Some(value) => self
+                .value_usages
+                .get(subject.module())
+                .and_then(|values| values.get(&value)),
+        }
+    }
+
+    fn grandfathered_subjects(&self, name: &str) -> Vec<Subject> {
+        let mut out: Vec<Subject> = self
+            .matched_usages
+            .keys()
+            .map(|module| Subject::Module(module.to_string()))
+            .collect();
+
+        for (module, values) in self.value_usages.iter() {
+            for value in values.keys() {
+                out.push(Subject::with_value(module, value));
+            }
+        }
+
+        if !self.usages.is_empty() {
+            out.push(Subject::Module(name.to_string()));
+        }
+
+        out
+    }
+
+    fn set_usages(&mut self, name: &str, found: BTreeMap<Subject, BTreeMap<PathBuf, usize>>) {
+        self.usages = Usages::default();
+        self.matched_usages = BTreeMap::new();
+        self.value_usages = BTreeMap::new();
+
+        for (subject, counts) in found {
+            let usages = if self.ratchet {
+                Usages::Counts(counts)
+            } else {
+                Usages::Files(counts.into_keys().collect())
+            };
+
+            match subject.value() {
+                None if subject.module() == name => self.usages = usages,
+                None => {
+                    self.matched_usages
+                        .insert(subject.module().to_string(), usages);
+                }
+                Some(value) => {
+                    self.value_usages
+                        .entry(subject.module().to_string())
+                        .or_default()
+                        .insert(value, usages);
+                }
+            }
+        }
+    }
+
+    /// The module to import instead. Patterns can match lots of modules, so
+    /// a single replacement can't be right for all of them (even if someone
+    /// added one to the config by hand.)
+    fn replacement_for(&self, name: &str) -> Option<&str> {
+        if ModulePattern::is_glob(name) {
+            None
+        } else {
+            self.replacement.as_deref()
+        }
+    }
+
+    /// We can only count usages if we look at the module body too.
+    fn wants_references(&self) -> bool {
+        self.scan_body || self.ratchet
+    }
+
+    fn is_whole_module(&self) -> bool {
+        self.exposing.is_empty() && !self.exposing_everything
+    }
+
+    /// Find all the places this rule forbids, grouped by what exactly is
+    /// forbidden about them.
+    fn found_usages<'a>(
+        &self,
+        name: &str,
+        parent_path: &Path,
+        imports_to_files: &'a BTreeMap<String, BTreeSet<importfinder::FoundImport>>,
+    ) -> Result<BTreeMap<Subject, Vec<Site<'a>>>> {
+        let pattern = ModulePattern::new(name)?;
+        let scope = PathScope::new(&self.include, &self.exclude).with_context(|| {
+            format!("could not read the include and exclude globs for {}", name)
+        })?;
+
+        let mut roots = BTreeSet::new();
+        for root in self.roots.iter() {
+            roots.insert(parent_path.join(root).canonicalize().with_context(|| {
+                format!(
+                    "could not find the project root {} for {}",
+                    root.display(),
+                    name
+                )
+            })?);
+        }
+
+        let mut out: BTreeMap<Subject, Vec<Site>> = BTreeMap::new();
+
+        for (import, found_imports) in imports_to_files.iter() {
+            if !pattern.matches(import) {
+                continue;
+            }
+
+            for found in found_imports {
+                let in_scope = pathdiff::diff_paths(&found.path, parent_path)
+                    .map(|path| scope.contains(&path))
+                    .unwrap_or(false);
+
+                if !in_scope || (!roots.is_empty() && roots.is_disjoint(&found.roots)) {
+                    continue;
+                }
+
+                for subject in self.forbidden_exposed_by(import, found) {
+                    out.entry(subject).or_default().push(Site {
+                        found,
+                        reference: None,
+                    });
+                }
+
+                if !self.wants_references() {
+                    continue;
+                }
+
+                for reference in found.references.iter() {
+                    let subject = if self.is_whole_module() {
+                        Subject::Module(import.to_string())
+                    } else if self.exposing.contains(&reference.name) {
+                        Subject::Value {
+                            module: import.to_string(),
+                            name: reference.name.to_string(),
+                        }
+                    } else {
+                        continue;
+                    };
+
+                    out.entry(subject).or_default().push(Site {
+                        found,
+                        reference: Some(reference),
+                    });
+                }
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// What does this import bring in that we don't want?
+    fn forbidden_exposed_by(
+        &self,
+        import: &str,
+        found: &importfinder::FoundImport,
+    ) -> Vec<Subject> {
+        if self.is_whole_module() {
+            return vec![Subject::Module(import.to_string())];
+        }
+
+        let value = |name: &String| Subject::Value {
+            module: import.to_string(),
+            name: name.to_string(),
+        };
+
+        match &found.exposing {
+            None => Vec::new(),
+            Some(Exposing::Everything) => {
+                if self.exposing_everything {
+                    vec![Subject::ExposingEverything(import.to_string())]
+                } else {
+                    self.exposing.iter().map(value).collect()
+                }
+            }
+            Some(Exposing::Explicit(exposed)) => exposed
+                .iter()
+                .map(|item| item.name())
+                .filter(|name| self.exposing.contains(name))
+                .map(|name| value(&name))
+                .collect(),
+        }
+    }
+}
+
+/// The specific thing a forbidden import rule matched.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Subject {
+    /// `Html.Attributes`
+    Module(String),
+
+    /// `Html.Attributes.style`
+    Value { module: String, name: String },
+
+    /// `Json.Decode exposing (..)`
+    ExposingEverything(String),
+}
+
+impl Subject {
+    /// The opposite of `value`: `..` means `exposing (..)`
+    fn with_value(module: &str, value: &str) -> Subject {
+        if value == ".." {
+            Subject::ExposingEverything(module.to_string())
+        } else {
+            Subject::Value {
+                module: module.to_string(),
+                name: value.to_string(),
+            }
+        }
+    }
+
+    fn module(&self) -> &str {
+        match self {
+            Subject::Module(module) => module,
+            Subject::Value { module, .. } => module,
+            Subject::ExposingEverything(module) => module,
+        }
+    }
+
+    /// The part of the subject beyond the module name, if any. `..` stands for
+    /// `exposing (..)`
+    fn value(&self) -> Option<String> {
+        match self {
+            Subject::Module(_) => None,
+            Subject::Value { name, .. } => Some(name.to_string()),
+            Subject::ExposingEverything(_) => Some(String::from("..")),
+        }
+    }
 }
 
-fn btreeset_is_empty(roots: &BTreeSet<PathBuf>) -> bool {
-    roots.is_empty()
+impl Display for Subject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Subject::Module(module) => write!(f, "{}", module),
+            Subject::Value { module, name } => write!(f, "{}.{}", module, name),
+            Subject::ExposingEverything(module) => write!(f, "{} exposing (..)", module),
+        }
+    }
+}
+
+/// One place where a forbidden thing shows up: either the import itself or a
+/// reference in the module body.
+#[derive(Debug)]
+struct Site<'a> {
+    found: &'a importfinder::FoundImport,
+    reference: Option<&'a Reference>,
+}
+
+impl Site<'_> {
+    fn position(&self) -> importfinder::Position {
+        match self.reference {
+            Some(reference) => reference.position,
+            None => self.found.position,
+        }
+    }
+}
+
+/// Operators are written with parentheses in exposing lists, but it's easy
+/// to forget them on the command line.
+fn normalize_exposed_name(name: String) -> String {
+    if name.starts_with(|c: char| c.is_alphabetic() || c == '(') {
+        name
+    } else {
+        format!("({})", name)
+    }
+}
+
+fn btreeset_is_empty<T>(set: &BTreeSet<T>) -> bool {
+    set.is_empty()
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+fn matched_usages_is_empty(matched: &BTreeMap<String, Usages>) -> bool {
+    matched.is_empty()
+}
+
+fn value_usages_is_empty(values: &BTreeMap<String, BTreeMap<String, Usages>>) -> bool {
+    values.is_empty()
+}
+
+fn layers_is_empty(layers: &BTreeMap<String, Layer>) -> bool {
+    layers.is_empty()
+}
+
+fn forbidden_packages_is_empty(packages: &BTreeMap<String, ForbiddenPackage>) -> bool {
+    packages.is_empty()
 }
 
 fn forbidden_is_empty(forbidden: &BTreeMap<String, ForbiddenImport>) -> bool {
@@ -40,7 +850,7 @@ fn forbidden_is_empty(forbidden: &BTreeMap<String, ForbiddenImport>) -> bool {
 
 impl Store {
     pub fn from_file_or_empty(path: &PathBuf) -> Result<Store> {
-        match fs::read(&path) {
+        match fs::read(path) {
             Ok(source) => {
                 let mut out: Store = toml::from_slice(&source)
                     .context("could not read TOML from the config file")?;
@@ -52,29 +862,176 @@ impl Store {
                 io::ErrorKind::NotFound => Ok(Store {
                     config_path: path.to_owned(),
                     roots: BTreeSet::new(),
+                    auto_roots: BTreeSet::new(),
                     forbidden: BTreeMap::new(),
+                    layers: BTreeMap::new(),
+                    allowed: AllowList::default(),
+                    forbidden_packages: BTreeMap::new(),
+                    finder: OnceCell::new(),
                 }),
                 _ => Err(anyhow!(err)),
             },
         }
     }
 
-    pub fn forbid(&mut self, name: String, hint: Option<String>) {
+    pub fn forbid(&mut self, name: String, options: ForbidOptions) -> Result<()> {
+        if options.replacement.is_some() && ModulePattern::is_glob(&name) {
+            bail!(
+                "`{}` is a pattern, so I can't replace every module it matches with the same one. Add a rule for each module you want to replace instead.",
+                name
+            );
+        }
+
+        let exposing = options
+            .exposing
+            .into_iter()
+            .map(normalize_exposed_name)
+            .collect();
+        let include: BTreeSet<String> = options.include.into_iter().collect();
+        let exclude: BTreeSet<String> = options.exclude.into_iter().collect();
+
+        // make sure the globs are valid now instead of when we check
+        PathScope::new(&include, &exclude)?;
+
+        let mut roots = BTreeSet::new();
+        for root in options.roots {
+            roots.insert(
+                self.relative_to_config_path(root)
+                    .context("could not find a path from the config file to the project root")?,
+            );
+        }
+
         if let Some(value) = self.forbidden.get_mut(&name) {
-            value.hint = hint
+            value.hint = options.hint;
+            value.replacement = options.replacement;
+            value.owner = options.owner;
+            value.ticket = options.ticket;
+            value.reason = options.reason;
+            value.exposing = exposing;
+            value.exposing_everything = options.exposing_everything;
+            value.scan_body = options.scan_body;
+            value.ratchet = options.ratchet;
+            value.include = include;
+            value.exclude = exclude;
+            value.roots = roots;
+            value.severity = options.severity;
+            value.deadline = options.deadline;
         } else {
             self.forbidden.insert(
                 name,
                 ForbiddenImport {
-                    hint,
-                    usages: BTreeSet::new(),
+                    hint: options.hint,
+                    replacement: options.replacement,
+                    owner: options.owner,
+                    ticket: options.ticket,
+                    reason: options.reason,
+                    exposing,
+                    exposing_everything: options.exposing_everything,
+                    scan_body: options.scan_body,
+                    ratchet: options.ratchet,
+                    include,
+                    exclude,
+                    roots,
+                    severity: options.severity,
+                    deadline: options.deadline,
+                    usages: Usages::default(),
+                    matched_usages: BTreeMap::new(),
+                    value_usages: BTreeMap::new(),
                 },
             );
         };
+
+        // this might change whether we scan module bodies
+        self.finder.take();
+
+        Ok(())
+    }
+
+    /// Like `forbid`, but for rows from a CSV, which can only say the hint,
+    /// owner, ticket, and reason. Rules we already have keep the rest of
+    /// their settings (severity, ratchet, replacement, and so on.)
+    pub fn forbid_from_csv(&mut self, name: String, options: ForbidOptions) -> Result<()> {
+        match self.forbidden.get_mut(&name) {
+            Some(value) => {
+                value.hint = options.hint;
+                value.owner = options.owner;
+                value.ticket = options.ticket;
+                value.reason = options.reason;
+
+                Ok(())
+            }
+            None => self.forbid(name, options),
+        }
     }
 
     pub fn unforbid(&mut self, name: String) {
         self.forbidden.remove(&name);
+        self.finder.take();
+    }
+
+    pub fn add_layer(&mut self, name: String, modules: Vec<String>, may_import: Vec<String>) {
+        let modules = modules.into_iter().collect();
+        let may_import = may_import.into_iter().collect();
+
+        if let Some(layer) = self.layers.get_mut(&name) {
+            layer.modules = modules;
+            layer.may_import = may_import;
+        } else {
+            self.layers.insert(
+                name,
+                Layer {
+                    modules,
+                    may_import,
+                    usages: BTreeMap::new(),
+                },
+            );
+        }
+    }
+
+    pub fn remove_layer(&mut self, name: String) {
+        self.layers.remove(&name);
+    }
+
+    pub fn forbid_package(&mut self, name: String, hint: Option<String>) -> Result<()> {
+        if name.split('/').count() != 2 {
+            bail!(
+                "`{}` doesn't look like a package name. It should look like `author/name`.",
+                name
+            );
+        }
+
+        if let Some(value) = self.forbidden_packages.get_mut(&name) {
+            value.hint = hint;
+        } else {
+            self.forbidden_packages.insert(
+                name,
+                ForbiddenPackage {
+                    hint,
+                    usages: BTreeMap::new(),
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn unforbid_package(&mut self, name: String) {
+        self.forbidden_packages.remove(&name);
+    }
+
+    pub fn allow(&mut self, names: Vec<String>) -> Result<()> {
+        for name in names {
+            ModulePattern::new(&name)?;
+            self.allowed.modules.insert(name);
+        }
+
+        Ok(())
+    }
+
+    pub fn unallow(&mut self, names: Vec<String>) {
+        for name in names {
+            self.allowed.modules.remove(&name);
+        }
     }
 
     fn relative_to_config_path(&self, path: PathBuf) -> Result<PathBuf> {
@@ -94,209 +1051,1029 @@ impl Store {
         }
     }
 
-    fn absolute_config_parent_path(&self) -> Result<PathBuf> {
-        match self.config_path.parent() {
-            Some(parent) => {
-                if parent.as_os_str().is_empty() {
-                    std::env::current_dir().context("could not get the current working directory")
-                } else {
-                    parent
-                        .canonicalize()
-                        .context("could not make an absolute path to the config's parent directory")
-                }
-            }
+    fn absolute_config_parent_path(&self) -> Result<PathBuf> {
+        match self.config_path.parent() {
+            Some(parent) => {
+                if parent.as_os_str().is_empty() {
+                    std::env::current_dir().context("could not get the current working directory")
+                } else {
+                    parent
+                        .canonicalize()
+                        .context("could not make an absolute path to the config's parent directory")
+                }
+            }
+
+            None => Err(anyhow!(
+                "config path ({}) does not have a parent.",
+                self.config_path.display(),
+            )),
+        }
+    }
+
+    fn absolute_from_config_path(&self, path: PathBuf) -> Result<PathBuf> {
+        let parent_path = self.absolute_config_parent_path()?;
+
+        parent_path.join(&path).canonicalize().with_context(|| {
+            format!(
+                "could not make an absolute path with the config file at {} and {}",
+                parent_path.display(),
+                path.display(),
+            )
+        })
+    }
+
+    pub fn add_root(&mut self, path: PathBuf) -> Result<()> {
+        importfinder::ElmJson::from_root(&path)
+            .context("the new project root doesn't look like an Elm project")?;
+
+        self.roots.insert(
+            self.relative_to_config_path(path)
+                .context("could not find a path from the config file to the new project root")?,
+        );
+        self.finder.take();
+
+        Ok(())
+    }
+
+    pub fn remove_root(&mut self, path: PathBuf) -> Result<()> {
+        let relative = self
+            .relative_to_config_path(path)
+            .context("could not find a path from the config file to the project root to remove")?;
+
+        self.roots.remove(&relative);
+        self.auto_roots.remove(&relative);
+        self.finder.take();
+
+        Ok(())
+    }
+
+    /// Find every project under a directory and add it as a root. Returns
+    /// the roots we found so we can tell people about them.
+    pub fn discover_roots(&mut self, path: PathBuf) -> Result<BTreeSet<PathBuf>> {
+        let found = importfinder::discover_roots(&path)
+            .with_context(|| format!("could not look for project roots in {}", path.display()))?;
+
+        for root in found.iter() {
+            self.add_root(root.to_owned())?;
+        }
+
+        Ok(found)
+    }
+
+    /// Look for project roots under a directory every time we scan, so new
+    /// projects get picked up without having to add them.
+    pub fn add_auto_roots(&mut self, path: PathBuf) -> Result<()> {
+        self.auto_roots.insert(
+            self.relative_to_config_path(path)
+                .context("could not find a path from the config file to the directory")?,
+        );
+        self.finder.take();
+
+        Ok(())
+    }
+
+    pub fn write(&self) -> Result<()> {
+        let serialized =
+            toml::to_string_pretty(self).context("could not serialize the store to TOML")?;
+
+        if serialized.is_empty() && self.config_path.exists() {
+            fs::remove_file(&self.config_path)
+                .context("could not remove the newly-empty config file")?;
+        } else {
+            fs::write(
+                &self.config_path,
+                String::from(AUTOGEN_HEADER) + &serialized,
+            )
+            .context("could not write the new config file to disk")?;
+        }
+
+        Ok(())
+    }
+
+    pub fn update(&mut self) -> Result<()> {
+        let imports_to_files = self
+            .scan()
+            .context("could not scan the project roots for Elm files")?;
+
+        let parent_path = self
+            .absolute_config_parent_path()
+            .context("could not get parent path to write new usages")?;
+
+        for (name, value) in self.forbidden.iter_mut() {
+            let found = value
+                .found_usages(name, &parent_path, &imports_to_files)?
+                .into_iter()
+                .map(|(subject, sites)| {
+                    let mut counts: BTreeMap<PathBuf, usize> = BTreeMap::new();
+
+                    for site in sites {
+                        if let Some(path) = pathdiff::diff_paths(&site.found.path, &parent_path) {
+                            *counts.entry(path).or_default() += 1;
+                        }
+                    }
+
+                    (subject, counts)
+                })
+                .collect();
+
+            value.set_usages(name, found);
+        }
+
+        let layers = LayerPatterns::new(&self.layers)?;
+        let mut layer_usages = BTreeMap::new();
+
+        for (name, layer) in self.layers.iter() {
+            let usages: BTreeMap<String, Usages> = layer
+                .found_usages(name, &layers, &imports_to_files)
+                .into_iter()
+                .map(|(subject, sites)| (subject.to_string(), files_usages(&sites, &parent_path)))
+                .collect();
+
+            layer_usages.insert(name.to_string(), usages);
+        }
+
+        for (name, usages) in layer_usages {
+            if let Some(layer) = self.layers.get_mut(&name) {
+                layer.usages = usages;
+            }
+        }
+
+        let mut package_usages = BTreeMap::new();
+
+        for (name, package) in self.forbidden_packages.iter() {
+            let modules = self
+                .finder()?
+                .package_modules(name)
+                .with_context(|| format!("could not find the modules in {}", name))?;
+
+            let usages: BTreeMap<String, Usages> = package
+                .found_usages(&modules, &imports_to_files)
+                .into_iter()
+                .map(|(subject, sites)| (subject.to_string(), files_usages(&sites, &parent_path)))
+                .collect();
+
+            package_usages.insert(name.to_string(), usages);
+        }
+
+        for (name, usages) in package_usages {
+            if let Some(package) = self.forbidden_packages.get_mut(&name) {
+                package.usages = usages;
+            }
+        }
+
+        let own_modules = self.own_modules()?;
+        self.allowed.usages = self
+            .allowed
+            .found_usages(&own_modules, &imports_to_files)?
+            .into_iter()
+            .map(|(subject, sites)| (subject.to_string(), files_usages(&sites, &parent_path)))
+            .collect();
+
+        Ok(())
+    }
+
+    pub fn check(&mut self, scope: &Scope) -> Result<Vec<CheckResult<'_>>> {
+        let parent_path = self
+            .absolute_config_parent_path()
+            .context("could not get parent path to check for new usages")?;
+
+        let (imports_to_files, in_scope) = match scope {
+            Scope::Everything => (
+                self.scan()
+                    .context("could not scan the project roots for Elm files")?,
+                None,
+            ),
+            Scope::Files(files) => (
+                self.finder()?
+                    .find_in(files)
+                    .context("could not scan the Elm files")?,
+                Some(
+                    files
+                        .iter()
+                        .filter_map(|file| pathdiff::diff_paths(file, &parent_path))
+                        .collect::<BTreeSet<PathBuf>>(),
+                ),
+            ),
+            Scope::Buffer { path, source } => {
+                let mut sources = BTreeMap::new();
+                sources.insert(path.to_owned(), source.to_owned());
+
+                (
+                    self.finder()?
+                        .find_in_sources(&sources)
+                        .context("could not scan the buffer")?,
+                    Some(
+                        pathdiff::diff_paths(path, &parent_path)
+                            .into_iter()
+                            .collect(),
+                    ),
+                )
+            }
+        };
+        let in_scope = in_scope.as_ref();
+
+        let current_dir =
+            std::env::current_dir().context("could not get the current working directory")?;
+
+        let mut out = Vec::new();
+
+        let no_usages = Usages::default();
+        let today = Local::now().date_naive();
+
+        for (name, existing) in self.forbidden.iter() {
+            let found_usages = existing.found_usages(name, &parent_path, &imports_to_files)?;
+
+            let subjects: BTreeMap<String, Subject> = existing
+                .grandfathered_subjects(name)
+                .into_iter()
+                .chain(found_usages.keys().cloned())
+                .map(|subject| (subject.to_string(), subject))
+                .collect();
+
+            for subject in subjects.into_values() {
+                out.extend(self.compare(
+                    &subject,
+                    existing.usages_of(name, &subject).unwrap_or(&no_usages),
+                    &sites_by_file(found_usages.get(&subject), &parent_path),
+                    &Rule {
+                        name: Some(name),
+                        hint: existing.hint.as_deref().map(Cow::Borrowed),
+                        replacement: existing.replacement_for(name),
+                        metadata: Metadata {
+                            owner: existing.owner.as_deref(),
+                            ticket: existing.ticket.as_deref(),
+                            reason: existing.reason.as_deref(),
+                        },
+                        severity: existing.severity,
+                        ratchet: existing.ratchet,
+                        deadline: DeadlineStatus::new(existing.deadline, today),
+                    },
+                    in_scope,
+                    &current_dir,
+                )?);
+            }
+        }
+
+        let layers = LayerPatterns::new(&self.layers)?;
+
+        for (name, layer) in self.layers.iter() {
+            out.extend(self.compare_modules(
+                &layer.usages,
+                &layer.found_usages(name, &layers, &imports_to_files),
+                |subject| match layers.layer_of(subject.module()) {
+                    Some(target) => Cow::Owned(format!(
+                        "the {} layer may not import the {} layer",
+                        name, target
+                    )),
+                    None => Cow::Owned(format!("the {} layer may not import this", name)),
+                },
+                &parent_path,
+                in_scope,
+                &current_dir,
+            )?);
+        }
+
+        let own_modules = self.own_modules()?;
+        out.extend(self.compare_modules(
+            &self.allowed.usages,
+            &self.allowed.found_usages(&own_modules, &imports_to_files)?,
+            |_| Cow::Borrowed("not in the allow list"),
+            &parent_path,
+            in_scope,
+            &current_dir,
+        )?);
+
+        for (name, package) in self.forbidden_packages.iter() {
+            let modules = self
+                .finder()?
+                .package_modules(name)
+                .with_context(|| format!("could not find the modules in {}", name))?;
+
+            out.extend(self.compare_modules(
+                &package.usages,
+                &package.found_usages(&modules, &imports_to_files),
+                |_| match &package.hint {
+                    Some(hint) => Cow::Borrowed(hint),
+                    None => Cow::Owned(format!("the {} package is forbidden", name)),
+                },
+                &parent_path,
+                in_scope,
+                &current_dir,
+            )?);
+        }
+
+        Ok(out)
+    }
+
+    /// Like `compare`, but for everything tracked as a set of files per
+    /// imported module (layers, the allow list, and packages.)
+    fn compare_modules<'a>(
+        &self,
+        usages: &BTreeMap<String, Usages>,
+        found_usages: &BTreeMap<Subject, Vec<Site>>,
+        hint: impl Fn(&Subject) -> Cow<'a, str>,
+        parent_path: &Path,
+        in_scope: Option<&BTreeSet<PathBuf>>,
+        current_dir: &Path,
+    ) -> Result<Vec<CheckResult<'a>>> {
+        let mut out = Vec::new();
+
+        let mut subjects: BTreeMap<String, Subject> = usages
+            .keys()
+            .map(|key| (key.to_string(), Subject::Module(key.to_string())))
+            .collect();
+        for subject in found_usages.keys() {
+            subjects.insert(subject.to_string(), subject.clone());
+        }
+
+        for (key, subject) in subjects {
+            out.extend(self.compare(
+                &subject,
+                usages.get(&key).unwrap_or(&Usages::default()),
+                &sites_by_file(found_usages.get(&subject), parent_path),
+                &Rule {
+                    name: None,
+                    hint: Some(hint(&subject)),
+                    replacement: None,
+                    metadata: Metadata::default(),
+                    severity: Severity::Error,
+                    ratchet: false,
+                    deadline: DeadlineStatus::None,
+                },
+                in_scope,
+                current_dir,
+            )?);
+        }
+
+        Ok(out)
+    }
+
+    /// Compare the places we found something against the places we've
+    /// previously allowed it, and report the differences. If we only looked
+    /// at some files (`in_scope`, relative to the config), we can't say
+    /// anything about the rest.
+    fn compare<'a>(
+        &self,
+        subject: &Subject,
+        existing_usages: &Usages,
+        to_sites: &BTreeMap<PathBuf, Vec<&Site>>,
+        rule: &Rule<'a>,
+        in_scope: Option<&BTreeSet<PathBuf>>,
+        current_dir: &Path,
+    ) -> Result<Vec<CheckResult<'a>>> {
+        let mut out = Vec::new();
 
-            None => Err(anyhow!(
-                "config path ({}) does not have a parent.",
-                self.config_path.display(),
-            )),
+        // swapping the import only makes sense where the import is, not at
+        // references in the module body
+        let replacement_at = |site: &Site| match site.reference {
+            Some(_) => None,
+            None => rule.replacement,
+        };
+
+        let new_usages = to_sites.keys().cloned().collect::<BTreeSet<PathBuf>>();
+        let mut existing_files = existing_usages.files();
+        if let Some(in_scope) = in_scope {
+            existing_files.retain(|file| in_scope.contains(file));
         }
-    }
 
-    fn absolute_from_config_path(&self, path: PathBuf) -> Result<PathBuf> {
-        let parent_path = self.absolute_config_parent_path()?;
+        for file in new_usages.difference(&existing_files) {
+            let nice_path = self.nice_path(file, current_dir)?;
 
-        parent_path.join(&path).canonicalize().with_context(|| {
-            format!(
-                "could not make an absolute path with the config file at {} and {}",
-                parent_path.display(),
-                path.display(),
-            )
-        })
-    }
+            for site in to_sites.get(file).into_iter().flatten() {
+                out.push(CheckResult {
+                    path: nice_path.to_owned(),
+                    position: Some(site.position()),
+                    import: subject.module().to_string(),
+                    value: subject.value(),
+                    suggested_replacement: replacement_at(site),
+                    metadata: rule.metadata,
+                    rule: rule.name,
+                    severity: rule.severity,
+                    error_location: ErrorLocation::InElmSource {
+                        hint: rule.hint.clone(),
+                        alias: site.found.alias.clone(),
+                        exposing: site.found.exposing.clone(),
+                        reference: site
+                            .reference
+                            .map(|reference| reference.written.to_string()),
+                        roots: site
+                            .found
+                            .roots
+                            .iter()
+                            .map(|root| {
+                                pathdiff::diff_paths(root, current_dir)
+                                    .unwrap_or_else(|| root.to_owned())
+                            })
+                            .collect(),
+                        in_exposed_module: site.found.in_exposed_module,
+                    },
+                });
+            }
+        }
 
-    pub fn add_root(&mut self, path: PathBuf) -> Result<()> {
-        self.roots.insert(
-            self.relative_to_config_path(path)
-                .context("could not find a path from the config file to the new project root")?,
-        );
+        for file in existing_files.difference(&new_usages) {
+            out.push(CheckResult {
+                path: self.nice_path(file, current_dir)?,
+                position: None,
+                import: subject.module().to_string(),
+                value: subject.value(),
+                suggested_replacement: None,
+                metadata: rule.metadata,
+                rule: rule.name,
+                severity: rule.severity,
+                error_location: ErrorLocation::InConfig,
+            })
+        }
 
-        Ok(())
-    }
+        for file in new_usages.intersection(&existing_files) {
+            let (severity, error_location) = match rule.deadline {
+                DeadlineStatus::None => continue,
+                DeadlineStatus::Approaching {
+                    deadline,
+                    days_left,
+                } => (
+                    rule.severity.min(Severity::Warn),
+                    ErrorLocation::DeadlineApproaching {
+                        hint: rule.hint.clone(),
+                        deadline,
+                        days_left,
+                    },
+                ),
+                DeadlineStatus::Past { deadline } => (
+                    Severity::Error,
+                    ErrorLocation::PastDeadline {
+                        hint: rule.hint.clone(),
+                        deadline,
+                    },
+                ),
+            };
 
-    pub fn remove_root(&mut self, path: PathBuf) -> Result<()> {
-        self.roots.remove(
-            &self.relative_to_config_path(path).context(
-                "could not find a path from the config file to the project root to remove",
-            )?,
-        );
+            let nice_path = self.nice_path(file, current_dir)?;
 
-        Ok(())
-    }
+            for site in to_sites.get(file).into_iter().flatten() {
+                out.push(CheckResult {
+                    path: nice_path.to_owned(),
+                    position: Some(site.position()),
+                    import: subject.module().to_string(),
+                    value: subject.value(),
+                    suggested_replacement: replacement_at(site),
+                    metadata: rule.metadata,
+                    rule: rule.name,
+                    severity,
+                    error_location: error_location.clone(),
+                });
+            }
+        }
 
-    pub fn write(&self) -> Result<()> {
-        let serialized =
-            toml::to_string_pretty(self).context("could not serialize the store to TOML")?;
+        // once we're past the deadline, the counts don't matter: everything
+        // has to go!
+        if !rule.ratchet || matches!(rule.deadline, DeadlineStatus::Past { .. }) {
+            return Ok(out);
+        }
 
-        if serialized.is_empty() && self.config_path.exists() {
-            fs::remove_file(&self.config_path)
-                .context("could not remove the newly-empty config file")?;
-        } else {
-            fs::write(
-                &self.config_path,
-                String::from(AUTOGEN_HEADER) + &serialized,
-            )
-            .context("could not write the new config file to disk")?;
+        for file in new_usages.intersection(&existing_files) {
+            let was = match existing_usages.count(file) {
+                Some(count) => count,
+                None => continue,
+            };
+
+            let sites = to_sites
+                .get(file)
+                .map(|sites| sites.as_slice())
+                .unwrap_or(&[]);
+            let now = sites.len();
+
+            if now > was {
+                out.push(CheckResult {
+                    path: self.nice_path(file, current_dir)?,
+                    position: sites.last().map(|site| site.position()),
+                    import: subject.module().to_string(),
+                    value: subject.value(),
+                    suggested_replacement: None,
+                    metadata: rule.metadata,
+                    rule: rule.name,
+                    severity: rule.severity,
+                    error_location: ErrorLocation::CountIncreased {
+                        hint: rule.hint.clone(),
+                        was,
+                        now,
+                    },
+                })
+            } else if now < was {
+                out.push(CheckResult {
+                    path: self.nice_path(file, current_dir)?,
+                    position: None,
+                    import: subject.module().to_string(),
+                    value: subject.value(),
+                    suggested_replacement: None,
+                    metadata: rule.metadata,
+                    rule: rule.name,
+                    severity: rule.severity,
+                    error_location: ErrorLocation::CountDecreased { was, now },
+                })
+            }
         }
 
-        Ok(())
+        Ok(out)
     }
 
-    pub fn update(&mut self) -> Result<()> {
+    /// Swap imports of forbidden modules for their replacements (if the
+    /// rule has one.) This doesn't write anything; it returns the new source
+    /// for every file that would change.
+    pub fn fix(&self) -> Result<Vec<Fix>> {
         let imports_to_files = self
             .scan()
             .context("could not scan the project roots for Elm files")?;
 
         let parent_path = self
             .absolute_config_parent_path()
-            .context("could not get parent path to write new usages")?;
+            .context("could not get parent path to look for usages")?;
 
-        for (import, value) in self.forbidden.iter_mut() {
-            if let Some(new_usages) = imports_to_files.get(import) {
-                value.usages = new_usages
-                    .iter()
-                    .flat_map(|usage| pathdiff::diff_paths(&usage.path, &parent_path))
-                    .collect()
+        let current_dir =
+            std::env::current_dir().context("could not get the current working directory")?;
+
+        let mut renames: BTreeMap<PathBuf, BTreeMap<String, String>> = BTreeMap::new();
+
+        for (name, forbidden) in self.forbidden.iter() {
+            // patterns never get a replacement, since each module they
+            // match would need a different one
+            let replacement = match forbidden.replacement_for(name) {
+                Some(replacement) => replacement,
+                None => continue,
             };
+
+            for site in forbidden
+                .found_usages(name, &parent_path, &imports_to_files)?
+                .values()
+                .flatten()
+            {
+                renames
+                    .entry(site.found.path.to_owned())
+                    .or_default()
+                    .insert(site.found.import.to_string(), replacement.to_string());
+            }
         }
 
-        Ok(())
+        let mut out = Vec::new();
+
+        for (path, renames) in renames {
+            let before = fs::read_to_string(&path)
+                .with_context(|| format!("could not read {}", path.display()))?;
+            let after = fix::rewrite(&before, &renames)
+                .with_context(|| format!("could not fix the imports in {}", path.display()))?;
+
+            if before != after {
+                out.push(Fix {
+                    path: pathdiff::diff_paths(&path, &current_dir).unwrap_or(path),
+                    before,
+                    after,
+                })
+            }
+        }
+
+        Ok(out)
     }
 
-    pub fn check(&mut self) -> Result<Vec<CheckResult>> {
-        let imports_to_files = self
-            .scan()
+    /// Summarize how far along we are in getting rid of each forbidden
+    /// import.
+    pub fn report(&self) -> Result<Vec<Report<'_>>> {
+        let finder = self.finder()?;
+
+        let imports_to_files = finder
+            .find()
             .context("could not scan the project roots for Elm files")?;
+        let files_by_root = finder
+            .files_by_root()
+            .context("could not list the files in the project roots")?;
 
         let parent_path = self
             .absolute_config_parent_path()
-            .context("could not get parent path to check for new usages")?;
+            .context("could not get parent path to report on usages")?;
 
         let current_dir =
             std::env::current_dir().context("could not get the current working directory")?;
 
         let mut out = Vec::new();
 
-        for (import, existing) in self.forbidden.iter() {
-            if let Some(found_imports) = imports_to_files.get(import) {
-                let new_usages = found_imports
+        for (name, forbidden) in self.forbidden.iter() {
+            let found_usages = forbidden.found_usages(name, &parent_path, &imports_to_files)?;
+
+            let grandfathered_files: BTreeSet<PathBuf> = forbidden
+                .grandfathered_subjects(name)
+                .iter()
+                .filter_map(|subject| forbidden.usages_of(name, subject))
+                .flat_map(|usages| usages.files())
+                .collect();
+
+            let using: BTreeSet<&Path> = found_usages
+                .values()
+                .flatten()
+                .map(|site| site.found.path.as_path())
+                .collect();
+
+            out.push(Report {
+                module: name,
+                hint: forbidden.hint.as_deref(),
+                grandfathered_files: grandfathered_files.len(),
+                import_sites: found_usages.values().map(|sites| sites.len()).sum(),
+                roots: files_by_root
                     .iter()
-                    .flat_map(|found| pathdiff::diff_paths(&found.path, &parent_path))
-                    .collect::<BTreeSet<PathBuf>>();
+                    .map(|(root, files)| {
+                        RootReport::new(
+                            pathdiff::diff_paths(root, &current_dir)
+                                .unwrap_or_else(|| root.to_owned()),
+                            files
+                                .iter()
+                                .filter(|file| using.contains(file.as_path()))
+                                .count(),
+                            files.len(),
+                        )
+                    })
+                    .collect(),
+            })
+        }
 
-                let mut to_positions: BTreeMap<PathBuf, importfinder::Position> = BTreeMap::new();
+        Ok(out)
+    }
 
-                for import in found_imports.iter() {
-                    if let Some(p) = pathdiff::diff_paths(&import.path, &parent_path) {
-                        to_positions.insert(p, import.position);
-                    }
-                }
+    /// Count usages of each forbidden import at every `every`th commit
+    /// leading up to `rev` (at most `limit` of them), oldest first. This reads
+    /// the files out of git instead of the working copy, so the roots need to
+    /// be inside the same repository as the first one.
+    pub fn history(
+        &self,
+        rev: &str,
+        every: usize,
+        limit: Option<usize>,
+    ) -> Result<Vec<HistoryPoint<'_>>> {
+        if every == 0 {
+            bail!("I need to look at every 1st commit or more, not every 0th")
+        }
 
-                for file in new_usages.difference(&existing.usages) {
-                    let nice_path = pathdiff::diff_paths(
-                        &self
-                            .absolute_from_config_path(file.to_path_buf())
-                            .with_context(|| {
-                                format!("could not get an absolute path to {}", file.display())
-                            })?,
-                        &current_dir,
-                    )
-                    .unwrap_or_else(|| file.to_owned());
-
-                    out.push(CheckResult {
-                        path: nice_path,
-                        position: to_positions.get(file).copied(),
-                        import: import.to_string(),
-                        error_location: ErrorLocation::InElmSource {
-                            hint: existing.hint.as_ref(),
-                        },
-                    });
-                }
+        // without any roots configured, we look at the current directory, so
+        // there's always a first root to find the repository from
+        let current_dir =
+            std::env::current_dir().context("could not get the current working directory")?;
+        let roots = self.absolute_roots()?;
+        let mut repo = history::Repo::containing(roots.iter().next().unwrap_or(&current_dir))?;
 
-                for file in existing.usages.difference(&new_usages) {
-                    let nice_path = pathdiff::diff_paths(
-                        &self
-                            .absolute_from_config_path(file.to_path_buf())
-                            .with_context(|| {
-                                format!("could not get an absolute path to {}", file.display())
-                            })?,
-                        &current_dir,
-                    )
-                    .unwrap_or_else(|| file.to_owned());
-
-                    out.push(CheckResult {
-                        path: nice_path,
-                        position: None,
-                        import: import.to_string(),
-                        error_location: ErrorLocation::InConfig,
-                    })
-                }
+        let parent_path = self
+            .absolute_config_parent_path()
+            .context("could not get parent path to look for usages")?;
+
+        let mut commits: Vec<history::Commit> =
+            repo.commits(rev)?.into_iter().step_by(every).collect();
+        if let Some(limit) = limit {
+            commits.truncate(limit);
+        }
+
+        let mut out = Vec::new();
+
+        for commit in commits.into_iter().rev() {
+            let imports_to_files =
+                history::find_at(&mut repo, &commit.id, &roots, self.wants_references())
+                    .with_context(|| format!("could not scan commit {}", commit.id))?;
+
+            for (name, forbidden) in self.forbidden.iter() {
+                let found_usages = forbidden.found_usages(name, &parent_path, &imports_to_files)?;
+
+                let files: BTreeSet<&Path> = found_usages
+                    .values()
+                    .flatten()
+                    .map(|site| site.found.path.as_path())
+                    .collect();
+
+                out.push(HistoryPoint {
+                    commit: commit.id.clone(),
+                    date: commit.date.clone(),
+                    module: name,
+                    files: files.len(),
+                    sites: found_usages.values().map(|sites| sites.len()).sum(),
+                })
             }
         }
 
         Ok(out)
     }
 
+    fn nice_path(&self, file: &Path, current_dir: &Path) -> Result<PathBuf> {
+        // the file might have been deleted, in which case we can't
+        // canonicalize it, but we still want to tell people about it!
+        let absolute = match self.absolute_from_config_path(file.to_path_buf()) {
+            Ok(absolute) => absolute,
+            Err(_) => history::normalize(
+                &self
+                    .absolute_config_parent_path()
+                    .with_context(|| {
+                        format!("could not get an absolute path to {}", file.display())
+                    })?
+                    .join(file),
+            ),
+        };
+
+        Ok(pathdiff::diff_paths(&absolute, current_dir).unwrap_or_else(|| file.to_owned()))
+    }
+
+    /// The Elm files in the project roots' git repositories that changed
+    /// since `rev`, for checking with `Scope::Files`.
+    pub fn changed_since(&self, rev: &str) -> Result<BTreeSet<PathBuf>> {
+        let mut out = BTreeSet::new();
+
+        for root in self.absolute_roots()? {
+            out.extend(history::changed_files(&root, rev)?);
+        }
+
+        Ok(out)
+    }
+
     pub fn scan(&self) -> Result<BTreeMap<String, BTreeSet<importfinder::FoundImport>>> {
+        self.finder()?.find()
+    }
+
+    /// The modules defined in the project roots. We only need these for the
+    /// allow list, so don't bother looking if there isn't one.
+    fn own_modules(&self) -> Result<BTreeSet<String>> {
+        if self.allowed.modules.is_empty() {
+            return Ok(BTreeSet::new());
+        }
+
+        self.finder()?
+            .modules()
+            .cloned()
+            .context("could not find the modules in the project roots")
+    }
+
+    fn finder(&self) -> Result<&importfinder::ImportFinder> {
+        if let Some(finder) = self.finder.get() {
+            return Ok(finder);
+        }
+
+        let finder = importfinder::ImportFinder::new(self.absolute_roots()?)
+            .scan_bodies(self.wants_references());
+
+        Ok(self.finder.get_or_init(|| finder))
+    }
+
+    fn absolute_roots(&self) -> Result<BTreeSet<PathBuf>> {
         let mut absolute_roots = BTreeSet::new();
 
         for root in self.roots.iter() {
             absolute_roots.insert(self.absolute_from_config_path(root.to_owned())?);
         }
 
+        for dir in self.auto_roots.iter() {
+            let absolute_dir = self.absolute_from_config_path(dir.to_owned())?;
+
+            absolute_roots.extend(importfinder::discover_roots(&absolute_dir).with_context(
+                || format!("could not look for project roots in {}", dir.display()),
+            )?);
+        }
+
         if absolute_roots.is_empty() {
             absolute_roots
                 .insert(std::env::current_dir().context("could not get current directory")?);
         }
 
-        let finder = importfinder::ImportFinder::new(absolute_roots);
+        Ok(absolute_roots)
+    }
 
-        finder.find()
+    fn wants_references(&self) -> bool {
+        self.forbidden
+            .values()
+            .any(|forbidden| forbidden.wants_references())
     }
 }
 
+/// Which files to look at when checking.
+pub enum Scope {
+    /// Every Elm file in the project roots
+    Everything,
+
+    /// Only these files (as absolute paths.) Files that have been deleted
+    /// still count, so we can tell you to remove them from the config.
+    Files(BTreeSet<PathBuf>),
+
+    /// A single file (as an absolute path), using this source instead of
+    /// what's on disk
+    Buffer { path: PathBuf, source: String },
+}
+
 #[derive(Debug, Serialize)]
 pub struct CheckResult<'a> {
     path: PathBuf,
     position: Option<importfinder::Position>,
     import: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<String>,
+
+    /// A module that could be imported instead, if the rule has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suggested_replacement: Option<&'a str>,
+
     error_location: ErrorLocation<'a>,
+    severity: Severity,
+    #[serde(flatten)]
+    metadata: Metadata<'a>,
+
+    /// Which forbidden import rule this came from, if any
+    #[serde(skip)]
+    rule: Option<&'a str>,
 }
 
-#[derive(Debug, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 #[serde(rename_all = "snake_case")]
 enum ErrorLocation<'a> {
-    InElmSource { hint: Option<&'a String> },
+    InElmSource {
+        hint: Option<Cow<'a, str>>,
+        alias: Option<String>,
+        exposing: Option<Exposing>,
+        reference: Option<String>,
+
+        /// The project roots the file belongs to
+        roots: Vec<PathBuf>,
+
+        /// Whether this is in a module a package root exposes, which makes it
+        /// part of the package's public API
+        #[serde(skip_serializing_if = "is_false")]
+        in_exposed_module: bool,
+    },
     InConfig,
+    CountIncreased {
+        hint: Option<Cow<'a, str>>,
+        was: usize,
+        now: usize,
+    },
+    CountDecreased {
+        was: usize,
+        now: usize,
+    },
+    DeadlineApproaching {
+        hint: Option<Cow<'a, str>>,
+        deadline: NaiveDate,
+        days_left: i64,
+    },
+    PastDeadline {
+        hint: Option<Cow<'a, str>>,
+        deadline: NaiveDate,
+    },
 }
 
 impl CheckResult<'_> {
     pub fn error_is_in_config(&self) -> bool {
-        self.error_location == ErrorLocation::InConfig
+        matches!(
+            self.error_location,
+            ErrorLocation::InConfig | ErrorLocation::CountDecreased { .. }
+        )
+    }
+
+    /// Only errors fail the check. Everything else just gets shown.
+    pub fn is_error(&self) -> bool {
+        self.severity.is_error()
+    }
+
+    /// Running `update` won't make these go away.
+    pub fn is_past_deadline(&self) -> bool {
+        matches!(self.error_location, ErrorLocation::PastDeadline { .. })
     }
 }
 
-impl CheckResult<'_> {
+impl<'a> CheckResult<'a> {
+    pub fn import(&self) -> &str {
+        &self.import
+    }
+
+    pub fn position(&self) -> Option<importfinder::Position> {
+        self.position
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// The name of the forbidden import rule this came from, as written in
+    /// the config. This can be a pattern, unlike `import`.
+    pub fn rule(&self) -> Option<&'a str> {
+        self.rule
+    }
+
+    pub fn metadata(&self) -> Metadata<'a> {
+        self.metadata
+    }
+
+    pub fn suggested_replacement(&self) -> Option<&'a str> {
+        self.suggested_replacement
+    }
+
+    /// The alias the forbidden module was imported with, if this is about an
+    /// import in an Elm file.
+    pub fn alias(&self) -> Option<&str> {
+        match &self.error_location {
+            ErrorLocation::InElmSource { alias, .. } => alias.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Whether `position` points at a reference in the module body instead
+    /// of at the import.
+    pub fn is_reference(&self) -> bool {
+        matches!(
+            self.error_location,
+            ErrorLocation::InElmSource {
+                reference: Some(_),
+                ..
+            }
+        )
+    }
+
+    /// What `position` points at: the reference in the module body if there
+    /// is one, or the imported module's name otherwise.
+    pub fn written(&self) -> &str {
+        match &self.error_location {
+            ErrorLocation::InElmSource {
+                reference: Some(reference),
+                ..
+            } => reference,
+            _ => &self.import,
+        }
+    }
+
+    /// What's wrong (including the hint), but not where.
+    pub fn message(&self) -> String {
+        let exposed_string = match &self.value {
+            Some(value) => format!(" exposing ({})", value),
+            None => String::new(),
+        };
+
+        let hint_string = |hint: &Option<Cow<str>>| match hint {
+            Some(an_actual_hint) => format!(" ({})", an_actual_hint),
+            None => String::new(),
+        };
+
+        match &self.error_location {
+            ErrorLocation::InElmSource {
+                hint,
+                reference: Some(_),
+                ..
+            } => {
+                let value_string = match &self.value {
+                    Some(value) => format!(".{}", value),
+                    None => String::new(),
+                };
+
+                format!(
+                    "forbidden use of {}{}{}",
+                    self.import,
+                    value_string,
+                    hint_string(hint),
+                )
+            }
+            ErrorLocation::InElmSource { hint, .. } => format!(
+                "forbidden import {}{}{}",
+                self.import,
+                exposed_string,
+                hint_string(hint),
+            ),
+            ErrorLocation::InConfig => format!(
+                "removed forbidden import {}{}! (Run me with `update` to fix this.)",
+                self.import, exposed_string,
+            ),
+            ErrorLocation::CountIncreased { hint, was, now } => format!(
+                "forbidden import {}{} is used {}, up from {}{}",
+                self.import,
+                exposed_string,
+                times(*now),
+                was,
+                hint_string(hint),
+            ),
+            ErrorLocation::DeadlineApproaching {
+                hint,
+                deadline,
+                days_left,
+            } => format!(
+                "forbidden import {}{} has to be gone by {} ({}){}",
+                self.import,
+                exposed_string,
+                deadline,
+                days_left_string(*days_left),
+                hint_string(hint),
+            ),
+            ErrorLocation::PastDeadline { hint, deadline } => format!(
+                "forbidden import {}{} should have been gone by {}{}",
+                self.import,
+                exposed_string,
+                deadline,
+                hint_string(hint),
+            ),
+            ErrorLocation::CountDecreased { was, now } => format!(
+                "forbidden import {}{} is used {}, down from {}! (Run me with `update` to fix this.)",
+                self.import,
+                exposed_string,
+                times(*now),
+                was,
+            ),
+        }
+    }
+
     pub fn relative_path(&self) -> PathBuf {
         std::env::current_dir()
             .ok()
@@ -307,33 +2084,124 @@ impl CheckResult<'_> {
 
 impl Display for CheckResult<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self.error_location {
-            ErrorLocation::InElmSource { hint } => {
-                let hint_string = match hint {
-                    Some(an_actual_hint) => format!(" ({})", an_actual_hint),
-                    None => String::new(),
-                };
+        if self.error_is_in_config() {
+            return write!(
+                f,
+                "{}: {}{}",
+                self.relative_path().display(),
+                self.severity.prefix(),
+                self.message(),
+            );
+        }
 
-                let position_string = match &self.position {
-                    Some(position) => format!(":{}:{}", position.row, position.column),
-                    None => String::new(),
-                };
+        let position_string = match &self.position {
+            Some(position) => format!(":{}:{}", position.row, position.column),
+            None => String::new(),
+        };
 
-                write!(
-                    f,
-                    "{}{}:forbidden import {}{}",
-                    self.relative_path().display(),
-                    position_string,
-                    self.import,
-                    hint_string,
-                )
-            }
-            ErrorLocation::InConfig => write!(
+        write!(
+            f,
+            "{}{}:{}{}{}",
+            self.relative_path().display(),
+            position_string,
+            self.severity.prefix(),
+            self.message(),
+            self.metadata,
+        )
+    }
+}
+
+/// How far along we are in getting rid of a forbidden import.
+#[derive(Debug, Serialize)]
+pub struct Report<'a> {
+    module: &'a str,
+    hint: Option<&'a str>,
+
+    /// How many files are allowed to use this in the config
+    grandfathered_files: usize,
+
+    /// How many places use this right now (imports, plus references in the
+    /// module body if we're scanning for those)
+    import_sites: usize,
+
+    roots: Vec<RootReport>,
+}
+
+/// How much a forbidden import was used at some commit.
+#[derive(Debug, Serialize)]
+pub struct HistoryPoint<'a> {
+    commit: String,
+    date: String,
+    module: &'a str,
+
+    /// How many files used it
+    files: usize,
+
+    /// How many places used it (imports, plus references in the module body
+    /// if we're scanning for those)
+    sites: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct RootReport {
+    root: PathBuf,
+    files_using: usize,
+    total_files: usize,
+    percent: f64,
+}
+
+impl RootReport {
+    fn new(root: PathBuf, files_using: usize, total_files: usize) -> RootReport {
+        RootReport {
+            root,
+            files_using,
+            total_files,
+            percent: if total_files == 0 {
+                0.0
+            } else {
+                files_using as f64 / total_files as f64 * 100.0
+            },
+        }
+    }
+}
+
+impl Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.hint {
+            Some(hint) => writeln!(f, "{} ({})", self.module, hint)?,
+            None => writeln!(f, "{}", self.module)?,
+        }
+
+        writeln!(f, "  grandfathered files: {}", self.grandfathered_files)?;
+        write!(f, "  import sites: {}", self.import_sites)?;
+
+        for root in self.roots.iter() {
+            write!(
                 f,
-                "{}: removed forbidden import {}! (Run me with `update` to fix this.)",
-                self.relative_path().display(),
-                self.import,
-            ),
+                "\n  {}: {} of {} files ({:.1}%)",
+                root.root.display(),
+                root.files_using,
+                root.total_files,
+                root.percent,
+            )?;
         }
+
+        Ok(())
+    }
+}
+
+fn days_left_string(days_left: i64) -> String {
+    match days_left {
+        0 => String::from("today!"),
+        1 => String::from("1 day left"),
+        _ => format!("{} days left", days_left),
+    }
+}
+
+fn times(count: usize) -> String {
+    if count == 1 {
+        String::from("once")
+    } else {
+        format!("{} times", count)
     }
 }
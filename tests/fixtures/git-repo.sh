@@ -0,0 +1,19 @@
+# Shared setup for tests that need a git repo with an Elm project in it.
+# Source this after setting REPO.
+
+rm -rf "$REPO"
+mkdir -p "$REPO/src"
+cp tests/fixtures/application/elm.json "$REPO/elm.json"
+
+git -c init.defaultBranch=main init --quiet "$REPO"
+
+export GIT_AUTHOR_NAME=test GIT_AUTHOR_EMAIL=test@example.com
+export GIT_COMMITTER_NAME=test GIT_COMMITTER_EMAIL=test@example.com
+
+# commit everything in the repo, optionally at a specific time
+# (commit MESSAGE [DATE])
+commit() {
+  export GIT_AUTHOR_DATE="${2:-2021-01-01T00:00:00Z}" GIT_COMMITTER_DATE="${2:-2021-01-01T00:00:00Z}"
+  git -C "$REPO" add -A
+  git -C "$REPO" -c commit.gpgsign=false commit --quiet --message "$1"
+}
@@ -0,0 +1,41 @@
+module Main exposing
+    ( main
+    , view
+    )
+
+{-| This module shows how to use `Html`:
+
+    import Html exposing (text)
+
+    main =
+        text "hi"
+
+-}
+
+{- we used to do this:
+import Html.Events
+{- nested comments are fine too -}
+import Html.Keyed
+-}
+
+import Html
+    exposing
+        ( Html
+        , div
+        , text
+        )
+-- import Html.Lazy
+import Html.Attributes
+    as Attr
+
+
+main : Html msg
+main =
+    view
+
+
+view : Html msg
+view =
+    div [ Attr.class "import Html.Events" ] [ text """
+import Html.Keyed
+""" ]
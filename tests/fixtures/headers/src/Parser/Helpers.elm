@@ -0,0 +1,27 @@
+module Parser.Helpers exposing (number)
+
+import Dict exposing (..)
+import Parser as P
+    exposing
+        ( (|.)
+        , (|=)
+        , Parser
+        , Step(..)
+        , succeed
+        )
+
+
+number : Parser Int
+number =
+    succeed identity
+        |= P.int
+        |. P.spaces
+
+
+type alias Config =
+    { succeed : Bool }
+
+
+toggle : Config -> Config
+toggle config =
+    { config | succeed = not config.succeed }
@@ -0,0 +1,6 @@
+port module Ports.Storage exposing (save)
+
+import Json.Encode as Encode exposing (Value)
+
+
+port save : Value -> Cmd msg
@@ -0,0 +1,22 @@
+effect module Task where { command = MyCmd } exposing
+    ( Task
+    , perform
+    )
+
+{-| -}
+
+import Basics exposing (..)
+import Platform.Cmd exposing (Cmd)
+
+
+type alias Task x a =
+    Platform.Task x a
+
+
+type MyCmd msg
+    = Perform (Task Never msg)
+
+
+perform : (a -> msg) -> Task Never a -> Cmd msg
+perform toMessage task =
+    command (Perform (Platform.map toMessage task))
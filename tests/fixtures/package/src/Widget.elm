@@ -0,0 +1,13 @@
+module Widget exposing (Widget, view)
+
+import Html exposing (Html)
+import Widget.Internal as Internal
+
+
+type Widget
+    = Widget Internal.Config
+
+
+view : Widget -> Html msg
+view (Widget config) =
+    Internal.view config
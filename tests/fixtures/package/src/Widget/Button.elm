@@ -0,0 +1,9 @@
+module Widget.Button exposing (button)
+
+import Html exposing (Html)
+import Html.Events
+
+
+button : msg -> String -> Html msg
+button onClick label =
+    Html.button [ Html.Events.onClick onClick ] [ Html.text label ]
@@ -0,0 +1,12 @@
+module Widget.Internal exposing (Config, view)
+
+import Html exposing (Html)
+
+
+type alias Config =
+    { label : String }
+
+
+view : Config -> Html msg
+view config =
+    Html.text config.label
@@ -0,0 +1,9 @@
+module Main exposing (main)
+
+import Html exposing (Html)
+import Shared
+
+
+main : Html msg
+main =
+    Shared.greeting "A"
@@ -0,0 +1,7 @@
+module Main exposing (main)
+
+import Shared
+
+
+main =
+    Shared.greeting "B"
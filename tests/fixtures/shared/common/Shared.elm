@@ -0,0 +1,8 @@
+module Shared exposing (greeting)
+
+import Html exposing (Html)
+
+
+greeting : String -> Html msg
+greeting name =
+    Html.text ("Hello, " ++ name)
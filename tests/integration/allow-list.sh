@@ -0,0 +1,14 @@
+#!/usr/bin/env bash
+
+elm-forbid-import add-root vendor/elm-spa-example
+
+# the project's own modules (like `Api` and `Page.Home`) are always allowed
+elm-forbid-import allow Browser 'Browser.*' Html 'Html.*' 'Json.*' Url 'Url.*' Http Task Time Dict
+
+if elm-forbid-import check; then
+  exit 1 # check should fail here!
+fi
+
+# grandfathering works the same way as for forbidden imports
+elm-forbid-import update
+elm-forbid-import check
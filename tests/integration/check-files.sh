@@ -0,0 +1,12 @@
+#!/usr/bin/env bash
+
+elm-forbid-import add-root vendor/elm-spa-example
+elm-forbid-import forbid Html --hint 'use Html.Styled'
+
+# only the first file imports Html, and the README isn't an Elm file at all
+if elm-forbid-import check vendor/elm-spa-example/src/Asset.elm vendor/elm-spa-example/src/Api.elm README.md; then
+  exit 1 # this check should exit 1
+fi
+
+elm-forbid-import update
+elm-forbid-import check vendor/elm-spa-example/src/Asset.elm vendor/elm-spa-example/src/Api.elm
@@ -0,0 +1,41 @@
+#!/usr/bin/env bash
+
+REPO=tmp/since-repo
+source tests/fixtures/git-repo.sh
+
+printf 'module A exposing (..)\n\nimport Html\n' > "$REPO/src/A.elm"
+printf 'module B exposing (..)\n\nimport Html\n' > "$REPO/src/B.elm"
+printf 'module C exposing (..)\n\nimport Html.Styled\n' > "$REPO/src/C.elm"
+
+elm-forbid-import add-root "$REPO"
+elm-forbid-import forbid Html
+elm-forbid-import update
+commit "add A, B, and C"
+
+# committed, so it's only new since the first commit
+printf 'module F exposing (..)\n\nimport Html\n' > "$REPO/src/F.elm"
+commit "add F"
+
+# uncommitted: a new usage, a deleted file, and an untracked file
+printf 'module C exposing (..)\n\nimport Html\n' > "$REPO/src/C.elm"
+rm "$REPO/src/B.elm"
+printf 'module D exposing (..)\n\nimport Html\n' > "$REPO/src/D.elm"
+
+if elm-forbid-import check --since HEAD; then
+  exit 1 # this check should exit 1
+fi
+
+if elm-forbid-import --format json check --since HEAD~1 | jq -c '.[]'; then
+  exit 1 # this check should exit 1
+fi
+
+# on a branch that's behind main, only what changed on the branch counts, not
+# whatever happened on main since we branched off
+commit "change C, remove B, and add D"
+elm-forbid-import update
+git -C "$REPO" checkout --quiet -b behind HEAD~1
+printf 'module G exposing (..)\n\nimport Html\n' > "$REPO/src/G.elm"
+
+if elm-forbid-import check --since main; then
+  exit 1 # this check should exit 1
+fi
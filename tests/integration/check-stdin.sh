@@ -0,0 +1,19 @@
+#!/usr/bin/env bash
+
+elm-forbid-import add-root vendor/elm-spa-example
+elm-forbid-import forbid Html --hint 'use Html.Styled'
+elm-forbid-import forbid Html.Events
+elm-forbid-import update
+
+# Asset.elm is allowed to import Html, but not Html.Events
+if sed 's/^import Html.Attributes as Attr$/&\nimport Html.Events/' vendor/elm-spa-example/src/Asset.elm | elm-forbid-import check --stdin --stdin-filename vendor/elm-spa-example/src/Asset.elm; then
+  exit 1 # this check should exit 1
+fi
+
+# a new file that isn't on disk yet
+if printf 'module Page.New exposing (..)\n\nimport Html\n' | elm-forbid-import --format json check --stdin --stdin-filename vendor/elm-spa-example/src/Page/New.elm | jq -c '.[]'; then
+  exit 1 # this check should exit 1
+fi
+
+# removing an import in an unsaved buffer isn't an error yet
+sed '/^import Html exposing/d' vendor/elm-spa-example/src/Asset.elm | elm-forbid-import --format editor check --stdin --stdin-filename vendor/elm-spa-example/src/Asset.elm
@@ -0,0 +1,23 @@
+#!/usr/bin/env bash
+
+SOON="$(date -d '+10 days' +%Y-%m-%d)"
+
+elm-forbid-import add-root vendor/elm-spa-example
+elm-forbid-import forbid Html.Events --deadline "$SOON" --hint 'use Html.Styled.Events'
+elm-forbid-import forbid Html --deadline 2000-01-01 --hint 'use Html.Styled'
+elm-forbid-import update
+
+# Html is past its deadline, so its usages are errors even though they're
+# grandfathered. Html.Events gets warnings.
+if elm-forbid-import check | sed "s/$SOON/SOON/g"; then
+  exit 1 # check should fail here!
+fi
+
+# warnings alone don't fail the check
+elm-forbid-import unforbid Html
+elm-forbid-import check | sed "s/$SOON/SOON/g"
+
+# and deadlines far in the future don't show up at all (this also keeps the
+# config the same from day to day)
+elm-forbid-import forbid Html.Events --deadline 2100-01-01 --hint 'use Html.Styled.Events'
+elm-forbid-import check
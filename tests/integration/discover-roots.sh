@@ -0,0 +1,21 @@
+#!/usr/bin/env bash
+
+export ELM_HOME=tests/fixtures/elm-home
+
+# this finds both the application and the package fixtures, but not the
+# packages in the fake ELM_HOME
+elm-forbid-import discover-roots tests/fixtures
+elm-forbid-import forbid Html
+
+if elm-forbid-import check; then
+  exit 1 # check should fail here!
+fi
+
+# in auto mode we look for roots every time we scan instead
+elm-forbid-import remove-root tests/fixtures/headers
+elm-forbid-import remove-root tests/fixtures/package
+elm-forbid-import discover-roots --auto vendor
+
+if elm-forbid-import check; then
+  exit 1 # check should fail here!
+fi
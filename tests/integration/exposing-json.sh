@@ -0,0 +1,9 @@
+#!/usr/bin/env bash
+
+elm-forbid-import add-root tests/fixtures/headers
+elm-forbid-import forbid Parser
+elm-forbid-import forbid Dict
+
+if elm-forbid-import --format json check | jq .; then
+  exit 1 # elm-forbid-import should exit with 1 here
+fi
@@ -0,0 +1,91 @@
+#!/usr/bin/env bash
+
+PROJECT=tmp/fix
+rm -rf "$PROJECT"
+mkdir -p "$PROJECT/src"
+cp tests/fixtures/application/elm.json "$PROJECT/elm.json"
+
+# aliases and exposing lists stay, and unaliased references get the new name
+cat > "$PROJECT/src/A.elm" <<ELM
+module A exposing (view)
+
+import Nri.Ui.Button.V9 as Button exposing (Style(..), button)
+import Nri.Ui.Icon.V1
+
+
+view =
+    button [ Button.primary ] [ Nri.Ui.Icon.V1.icon ]
+ELM
+
+# Html.Styled is already imported, so we merge into that instead
+cat > "$PROJECT/src/B.elm" <<ELM
+module B exposing (view)
+
+import Html exposing (Html, div)
+import Html.Events
+import Html.Styled exposing (text)
+
+
+view : Html msg
+view =
+    div [] [ Html.text "hi", text "there" ]
+ELM
+
+# imports split over several lines only get the name changed
+cat > "$PROJECT/src/C.elm" <<ELM
+module C exposing (..)
+
+import Nri.Ui.Button.V9
+    exposing
+        ( button
+        )
+
+
+x =
+    button
+ELM
+
+# files with Windows line endings keep them
+printf 'module D exposing (view)\r\n\r\nimport Html exposing (div)\r\nimport Html.Styled exposing (text)\r\n\r\n\r\nview =\r\n    div [] [ text "hi" ]\r\n' > "$PROJECT/src/D.elm"
+
+# references through an alias get whatever the merged import is called
+cat > "$PROJECT/src/E.elm" <<ELM
+module E exposing (view)
+
+import Html as H exposing (div)
+import Html.Styled as S exposing (text)
+
+
+view =
+    div [] [ H.text "hi", S.text "there", text "!" ]
+ELM
+
+cat > "$PROJECT/src/F.elm" <<ELM
+module F exposing (view)
+
+import Html.Styled exposing (text)
+import Html as H
+
+
+view =
+    H.div [] [ H.text "hi", text "there" ]
+ELM
+
+elm-forbid-import add-root "$PROJECT"
+elm-forbid-import forbid Nri.Ui.Button.V9 --replacement Nri.Ui.Button.V10
+elm-forbid-import forbid Nri.Ui.Icon.V1 --replacement Nri.Ui.Icon.V2
+elm-forbid-import forbid Html --hint 'use Html.Styled' --replacement Html.Styled
+elm-forbid-import forbid Html.Events
+elm-forbid-import update
+
+elm-forbid-import fix --dry-run
+elm-forbid-import fix
+tail -n +1 "$PROJECT"/src/*.elm | sed 's/\r$/<CR>/'
+
+# the config still allows the old imports until we run `update`
+if elm-forbid-import check; then
+  exit 1 # this check should exit 1
+fi
+
+# there's nothing left to do
+elm-forbid-import fix
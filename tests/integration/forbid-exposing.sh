@@ -0,0 +1,12 @@
+#!/usr/bin/env bash
+
+elm-forbid-import add-root vendor/elm-spa-example
+elm-forbid-import forbid Html --exposing-everything --hint 'expose only what you need'
+elm-forbid-import forbid Html.Events --exposing onClick --exposing onInput
+
+if elm-forbid-import check; then
+  exit 1 # check should fail here!
+fi
+
+elm-forbid-import update
+elm-forbid-import check
@@ -0,0 +1,18 @@
+#!/usr/bin/env bash
+
+export ELM_HOME=tests/fixtures/elm-home
+
+elm-forbid-import add-root vendor/elm-spa-example
+elm-forbid-import forbid-package elm-explorations/markdown --hint 'render with elm-markdown instead'
+elm-forbid-import forbid-package rtfeldman/elm-iso8601-date-strings
+
+if elm-forbid-import check; then
+  exit 1 # check should fail here!
+fi
+
+elm-forbid-import update
+elm-forbid-import check
+
+# forbidding the package again only changes the hint
+elm-forbid-import forbid-package elm-explorations/markdown --hint 'use dillonkearns/elm-markdown'
+elm-forbid-import check
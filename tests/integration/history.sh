@@ -0,0 +1,30 @@
+#!/usr/bin/env bash
+
+# build a tiny repo where we slowly get rid of Html
+REPO=tmp/history-repo
+source tests/fixtures/git-repo.sh
+
+printf 'module A exposing (..)\n\nimport Html\nimport Html.Attributes\n' > "$REPO/src/A.elm"
+printf 'module B exposing (..)\n\nimport Html\n' > "$REPO/src/B.elm"
+commit "add A and B" 2021-01-01T00:00:00Z
+
+printf 'module C exposing (..)\n\nimport Html\n' > "$REPO/src/C.elm"
+commit "add C" 2021-01-02T00:00:00Z
+
+printf 'module A exposing (..)\n\nimport Html.Styled\nimport Html.Attributes\n' > "$REPO/src/A.elm"
+commit "migrate A" 2021-01-03T00:00:00Z
+
+rm "$REPO/src/B.elm"
+commit "remove B" 2021-01-04T00:00:00Z
+
+# uncommitted changes shouldn't show up
+printf 'module D exposing (..)\n\nimport Html\n' > "$REPO/src/D.elm"
+
+elm-forbid-import add-root "$REPO"
+elm-forbid-import forbid Html
+elm-forbid-import forbid 'Html.*'
+
+elm-forbid-import history
+elm-forbid-import history --every 2
+elm-forbid-import history --limit 1
+elm-forbid-import --format json history | jq -c '.[]'
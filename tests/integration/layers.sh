@@ -0,0 +1,15 @@
+#!/usr/bin/env bash
+
+elm-forbid-import add-root vendor/elm-spa-example
+
+elm-forbid-import add-layer Page --module Page --module 'Page.*' --may-import Api
+elm-forbid-import add-layer Api --module Api --module 'Api.*'
+elm-forbid-import add-layer Data --module Article --module 'Article.*' --module Author --module Profile
+
+if elm-forbid-import check; then
+  exit 1 # check should fail here!
+fi
+
+# grandfathering works the same way as for forbidden imports
+elm-forbid-import update
+elm-forbid-import check
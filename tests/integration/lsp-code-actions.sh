@@ -0,0 +1,34 @@
+#!/usr/bin/env bash
+
+elm-forbid-import add-root vendor/elm-spa-example
+elm-forbid-import forbid Html --hint 'use Html.Styled' --replacement Html.Styled
+elm-forbid-import forbid Html.Attributes
+
+FILE="$PWD/vendor/elm-spa-example/src/Asset.elm"
+URI="file://$FILE"
+
+# the buffer we send refers to Html qualified, which has to keep working
+# after we swap the import
+TEXT="$(cat "$FILE")
+"'
+loadingIcon : Html msg
+loadingIcon =
+    Html.text "loading"'
+
+send() {
+  printf 'Content-Length: %d\r\n\r\n%s' "$(printf '%s' "$1" | wc -c)" "$1"
+}
+
+{
+  send '{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"capabilities":{}}}'
+  send "$(jq -cn --arg uri "$URI" --arg text "$TEXT" '{ jsonrpc: "2.0", method: "textDocument/didOpen", params: { textDocument: { uri: $uri, text: $text, languageId: "elm", version: 1 } } }')"
+
+  # Html (line 9) has a replacement, but Html.Attributes (line 10) doesn't
+  send "$(jq -cn --arg uri "$URI" '{ jsonrpc: "2.0", id: 2, method: "textDocument/codeAction", params: { textDocument: { uri: $uri }, range: { start: { line: 9, character: 0 }, end: { line: 10, character: 0 } }, context: { diagnostics: [] } } }')"
+
+  # nothing to do on line 0
+  send "$(jq -cn --arg uri "$URI" '{ jsonrpc: "2.0", id: 3, method: "textDocument/codeAction", params: { textDocument: { uri: $uri }, range: { start: { line: 0, character: 0 }, end: { line: 0, character: 0 } }, context: { diagnostics: [] } } }')"
+
+  send '{"jsonrpc":"2.0","id":4,"method":"shutdown"}'
+  send '{"jsonrpc":"2.0","method":"exit"}'
+} | elm-forbid-import lsp | tr -d '\r' | sed 's/Content-Length: [0-9]*/\n/g' | grep -v '^$' | sed "s|$PWD|ROOT|g" | jq -c 'select(.id == 2 or .id == 3)'
@@ -0,0 +1,38 @@
+#!/usr/bin/env bash
+
+elm-forbid-import add-root vendor/elm-spa-example
+elm-forbid-import forbid Html --hint 'use Html.Styled'
+elm-forbid-import update
+elm-forbid-import forbid Html.Events --hint 'use Html.Styled.Events' --owner '@frontend' --ticket 'T-123'
+
+FILE="$PWD/vendor/elm-spa-example/src/Asset.elm"
+URI="file://$FILE"
+
+send() {
+  printf 'Content-Length: %d\r\n\r\n%s' "$(printf '%s' "$1" | wc -c)" "$1"
+}
+
+document() {
+  jq -cn --arg uri "$URI" --arg text "$2" "{ jsonrpc: \"2.0\", method: \"$1\", params: { textDocument: { uri: \$uri, text: \$text, version: 1 }, contentChanges: [{ text: \$text }] } }"
+}
+
+{
+  send '{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"capabilities":{"workspace":{"didChangeWatchedFiles":{"dynamicRegistration":true}}}}}'
+  send '{"jsonrpc":"2.0","method":"initialized","params":{}}'
+
+  # Asset.elm is allowed to import Html, but not Html.Events
+  send "$(document textDocument/didOpen "$(sed 's/^import Html.Attributes as Attr$/&\nimport Html.Events/' "$FILE")")"
+  sleep 1
+
+  # changing the config should pick up the new rule, and point at the
+  # pattern in the config even though the import is more specific
+  elm-forbid-import forbid 'Html.A*' --reason 'attributes go through Html.Styled too' > /dev/null
+  send '{"jsonrpc":"2.0","method":"workspace/didChangeWatchedFiles","params":{"changes":[]}}'
+
+  send "$(document textDocument/didChange "$(cat "$FILE")")"
+  send "$(document textDocument/didClose "")"
+
+  send '{"jsonrpc":"2.0","id":2,"method":"textDocument/hover","params":{}}'
+  send '{"jsonrpc":"2.0","id":3,"method":"shutdown"}'
+  send '{"jsonrpc":"2.0","method":"exit"}'
+} | elm-forbid-import lsp | tr -d '\r' | sed 's/Content-Length: [0-9]*/\n/g' | grep -v '^$' | sed "s|$PWD|ROOT|g" | jq -c 'del(.result.serverInfo.version)'
@@ -0,0 +1,18 @@
+#!/usr/bin/env bash
+
+elm-forbid-import add-root vendor/elm-spa-example
+elm-forbid-import forbid Html --hint 'use Html.Styled' --owner '@frontend' --ticket 'https://tickets.example.com/123' --reason "we're moving to elm-css"
+elm-forbid-import forbid-from-csv tests/fixtures/forbidden-with-metadata.csv
+
+if elm-forbid-import --format editor check | grep Page/Home.elm; then
+  exit 1 # check should fail here!
+fi
+
+if elm-forbid-import --format json check | jq '.[] | select(.path | endswith("Page/Home.elm")) | {module: .import, owner, ticket, reason}'; then
+  exit 1 # check should fail here!
+fi
+
+# importing the CSV again only changes what the CSV has columns for
+elm-forbid-import forbid Html.Attributes --hint 'use Html.Styled.Attributes' --severity warn --replacement Html.Styled.Attributes --exclude 'src/Asset.elm'
+elm-forbid-import forbid-from-csv tests/fixtures/forbidden-with-metadata.csv
+grep -A4 'forbidden."Html.Attributes"' tmp/forbidden-imports.toml
@@ -0,0 +1,8 @@
+#!/usr/bin/env bash
+
+elm-forbid-import add-root tests/fixtures/package
+elm-forbid-import forbid Html --hint 'use Html.Styled'
+
+if elm-forbid-import --format json check; then
+  exit 1 # check should fail here!
+fi
@@ -0,0 +1,16 @@
+#!/usr/bin/env bash
+
+elm-forbid-import add-root tests/fixtures/shared/app-a
+elm-forbid-import add-root tests/fixtures/shared/app-b
+elm-forbid-import add-root vendor/elm-spa-example
+
+# Html is only forbidden in app-b, so we should see the shared module but not
+# app-a's Main or anything in the SPA
+elm-forbid-import forbid Html --root tests/fixtures/shared/app-b
+
+if elm-forbid-import check; then
+  exit 1 # check should fail here!
+fi
+
+elm-forbid-import update
+elm-forbid-import check
@@ -0,0 +1,37 @@
+#!/usr/bin/env bash
+
+elm-forbid-import add-root tests/fixtures/headers
+elm-forbid-import forbid Html.Attributes --ratchet
+elm-forbid-import update
+
+FILE=tests/fixtures/headers/src/Main.elm
+cp "$FILE" "$FILE.bak"
+trap 'mv "$FILE.bak" "$FILE"' EXIT
+
+# using it more should fail...
+printf '\n\nother : Html msg\nother =\n    div [ Attr.id "other" ] []\n' >> "$FILE"
+
+if elm-forbid-import check; then
+  exit 1 # check should fail here!
+fi
+
+# ... and using it less should ask us to update
+cp "$FILE.bak" "$FILE"
+sed -i 's/\[ Attr.class "import Html.Events" \]/[]/' "$FILE"
+
+if elm-forbid-import check; then
+  exit 1 # check should fail here too!
+fi
+
+# `exposing (..)` only counts qualified references, so unrelated code in the
+# module body doesn't change the count
+cp "$FILE.bak" "$FILE"
+elm-forbid-import forbid Dict --ratchet
+elm-forbid-import update
+
+HELPERS=tests/fixtures/headers/src/Parser/Helpers.elm
+cp "$HELPERS" "$HELPERS.bak"
+trap 'mv "$FILE.bak" "$FILE"; mv "$HELPERS.bak" "$HELPERS"' EXIT
+
+printf '\n\nhelper : Bool -> Int\nhelper flag =\n    case flag of\n        True ->\n            1\n\n        False ->\n            0\n' >> "$HELPERS"
+elm-forbid-import check
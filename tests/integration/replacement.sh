@@ -0,0 +1,15 @@
+#!/usr/bin/env bash
+
+elm-forbid-import add-root vendor/elm-spa-example
+elm-forbid-import forbid Html --hint 'use Html.Styled' --replacement Html.Styled
+elm-forbid-import forbid Html.Attributes --replacement Html.Styled.Attributes --exposing style --scan-body
+
+if elm-forbid-import --format json check | jq -c '.[0:2][], (.[] | select(.import == "Html.Attributes"))'; then
+  exit 1 # this check should exit 1
+fi
+
+# a pattern matches lots of modules, so one replacement can't be right for all
+# of them
+if elm-forbid-import forbid 'Html.*' --replacement Html.Styled; then
+  exit 1 # this should be rejected
+fi
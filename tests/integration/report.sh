@@ -0,0 +1,10 @@
+#!/usr/bin/env bash
+
+elm-forbid-import add-root vendor/elm-spa-example
+elm-forbid-import add-root tests/fixtures/shared/app-a
+elm-forbid-import forbid Html --hint 'use Html.Styled'
+elm-forbid-import update
+elm-forbid-import forbid Html.Events
+
+elm-forbid-import report
+elm-forbid-import --format json stats | jq -c '.[]'
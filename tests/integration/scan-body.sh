@@ -0,0 +1,35 @@
+#!/usr/bin/env bash
+
+elm-forbid-import add-root tests/fixtures/headers
+elm-forbid-import forbid Html.Attributes --exposing class --scan-body --hint 'use css'
+elm-forbid-import forbid Html --exposing text --scan-body
+elm-forbid-import forbid Parser --exposing succeed --exposing '|.' --scan-body
+
+if elm-forbid-import --format editor check; then
+  exit 1 # check should fail here!
+fi
+
+elm-forbid-import update
+elm-forbid-import check
+
+# `exposing (..)` could bring in anything, so only qualified references count
+# (not keywords, local definitions, or whatever else is in the module body)
+elm-forbid-import forbid Dict --scan-body
+
+if elm-forbid-import --format editor check; then
+  exit 1 # check should fail here!
+fi
+
+elm-forbid-import update
+
+FILE=tests/fixtures/headers/src/Parser/Helpers.elm
+cp "$FILE" "$FILE.bak"
+trap 'mv "$FILE.bak" "$FILE"' EXIT
+
+# getting rid of an operator should say so, instead of talking about a module
+# called `Parser.(|.)`
+sed -i -e '/( (|\.)/d' -e 's/, (|=)/( (|=)/' -e '/|\. P\.spaces/d' "$FILE"
+
+if elm-forbid-import --format json check; then
+  exit 1 # check should fail here!
+fi
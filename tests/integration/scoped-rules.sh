@@ -0,0 +1,13 @@
+#!/usr/bin/env bash
+
+elm-forbid-import add-root vendor/elm-spa-example
+
+# pages shouldn't be imported from the API layer...
+elm-forbid-import forbid 'Page.*' --include '../vendor/elm-spa-example/src/Api/**' --include '../vendor/elm-spa-example/src/Api.elm'
+
+# ... and only Main should be orchestrating pages
+elm-forbid-import forbid 'Page*' --exclude '../vendor/elm-spa-example/src/Main.elm' --hint 'only Main may import pages'
+
+if elm-forbid-import check; then
+  exit 1 # check should fail here!
+fi
@@ -0,0 +1,14 @@
+#!/usr/bin/env bash
+
+elm-forbid-import add-root vendor/elm-spa-example
+elm-forbid-import forbid Html.Events --severity warn --hint 'use Html.Styled.Events'
+elm-forbid-import forbid Browser.Dom --severity info
+
+# warnings and infos get shown, but don't fail the check
+elm-forbid-import check
+elm-forbid-import --format json check | jq -c '.[] | {path, severity}' | sed "s|$PWD/||g" | head -n 3
+
+elm-forbid-import forbid Html --hint 'use Html.Styled'
+if elm-forbid-import --format editor check | grep Page/Home.elm; then
+  exit 1 # check should fail here!
+fi
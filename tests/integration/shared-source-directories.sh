@@ -0,0 +1,10 @@
+#!/usr/bin/env bash
+
+# both apps use ../common, but Shared.elm should only be reported once
+elm-forbid-import add-root tests/fixtures/shared/app-a
+elm-forbid-import add-root tests/fixtures/shared/app-b
+elm-forbid-import forbid Html
+
+if elm-forbid-import --format json check | jq . | sed "s|$PWD/||g"; then
+  exit 1 # elm-forbid-import should exit with 1 here
+fi
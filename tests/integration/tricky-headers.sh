@@ -0,0 +1,10 @@
+#!/usr/bin/env bash
+
+elm-forbid-import add-root tests/fixtures/headers
+elm-forbid-import forbid 'Html*'
+elm-forbid-import forbid Json.Encode
+elm-forbid-import forbid Platform.Cmd
+
+if elm-forbid-import --format editor check; then
+  exit 1 # check should fail here!
+fi